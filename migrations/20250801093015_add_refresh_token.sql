@@ -0,0 +1,20 @@
+-- Add migration script here
+
+-- Refresh token table
+-- Every refresh token handed out is recorded here so it can be rotated and
+-- revoked. Tokens issued from the same login share a family, so that reuse
+-- of a rotated token can revoke every token descended from that login.
+CREATE TABLE IF NOT EXISTS refresh_token (
+    PRIMARY KEY (token_id),
+    token_id           CHAR(36)        NOT NULL UNIQUE,
+    family_id          CHAR(36)        NOT NULL,
+    nomer_id           INTEGER         NOT NULL,
+    issued_at          TIMESTAMP       NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at         TIMESTAMP       NOT NULL,
+    used_at            TIMESTAMP       NULL DEFAULT NULL,
+    revoked_at         TIMESTAMP       NULL DEFAULT NULL,
+    INDEX (family_id),
+    FOREIGN KEY (nomer_id) REFERENCES nomer(nomer_id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
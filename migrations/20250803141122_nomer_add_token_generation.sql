@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- Bumping the generation invalidates every access token issued before it
+ALTER TABLE nomer ADD COLUMN token_generation INTEGER NOT NULL DEFAULT 0;
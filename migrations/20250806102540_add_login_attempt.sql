@@ -0,0 +1,12 @@
+-- Add migration script here
+
+-- Login attempt table
+-- Failed logins are counted per throttle key (an account's email or a client
+-- address) so that instances behind a load balancer share one lockout.
+CREATE TABLE IF NOT EXISTS login_attempt (
+    PRIMARY KEY (throttle_key),
+    throttle_key       VARCHAR(255)    NOT NULL UNIQUE,
+    failures           INTEGER         NOT NULL,
+    last_failure_at    TIMESTAMP       NOT NULL,
+    locked_until       TIMESTAMP       NULL DEFAULT NULL
+);
@@ -0,0 +1,21 @@
+-- Add migration script here
+
+-- Email verification
+-- Nomers who signed up before verification existed are treated as verified.
+ALTER TABLE nomer ADD COLUMN email_verified_at TIMESTAMP NULL DEFAULT NULL;
+UPDATE nomer SET email_verified_at = CURRENT_TIMESTAMP;
+
+-- Email verification token table
+-- Every verification link sent is recorded here so that it can only be
+-- used once, and only for the address it was sent to.
+CREATE TABLE IF NOT EXISTS email_verification (
+    PRIMARY KEY (token_id),
+    token_id           CHAR(36)        NOT NULL UNIQUE,
+    nomer_id           INTEGER         NOT NULL,
+    email              VARCHAR(255)    NOT NULL,
+    issued_at          TIMESTAMP       NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    used_at            TIMESTAMP       NULL DEFAULT NULL,
+    FOREIGN KEY (nomer_id) REFERENCES nomer(nomer_id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- Password reset token table
+-- Only a SHA-256 hash of each token is stored, so that a leaked table
+-- cannot be used to take over accounts.
+CREATE TABLE IF NOT EXISTS password_reset (
+    PRIMARY KEY (token_hash),
+    token_hash         CHAR(64)        NOT NULL UNIQUE,
+    nomer_id           INTEGER         NOT NULL,
+    issued_at          TIMESTAMP       NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at         TIMESTAMP       NOT NULL,
+    used_at            TIMESTAMP       NULL DEFAULT NULL,
+    FOREIGN KEY (nomer_id) REFERENCES nomer(nomer_id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- Address a nomer asked to change to, which replaces `email` once verified
+ALTER TABLE nomer ADD COLUMN pending_email VARCHAR(255) NULL DEFAULT NULL;
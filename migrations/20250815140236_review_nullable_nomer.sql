@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- Reviews outlive their author if the author deletes their account but
+-- asks to keep them anonymously
+ALTER TABLE review MODIFY nomer_id INTEGER NULL;
@@ -0,0 +1,14 @@
+-- Add migration script here
+
+-- Public profile details
+-- Nomers who joined before sign-up dates were recorded are dated by their
+-- first review, if they have one.
+ALTER TABLE nomer
+    ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD COLUMN bio VARCHAR(280) NULL DEFAULT NULL,
+    ADD COLUMN avatar_url VARCHAR(2048) NULL DEFAULT NULL;
+UPDATE nomer
+SET created_at = COALESCE(
+    (SELECT MIN(review.created_at) FROM review WHERE review.nomer_id = nomer.nomer_id),
+    created_at
+);
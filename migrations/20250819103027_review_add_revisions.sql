@@ -0,0 +1,21 @@
+-- Add migration script here
+
+-- Reviews can be edited; updated_at stays NULL until they are
+ALTER TABLE review ADD COLUMN updated_at TIMESTAMP NULL DEFAULT NULL;
+
+-- Review revision table
+-- Every edit keeps the version of the review it replaced, so that
+-- moderators can see what changed. written_at is when that version was
+-- posted or last edited.
+CREATE TABLE IF NOT EXISTS review_revision (
+    PRIMARY KEY (revision_id),
+    revision_id        INTEGER         NOT NULL UNIQUE AUTO_INCREMENT,
+    review_id          INTEGER         NOT NULL,
+    score              INTEGER         NOT NULL,
+    comment            VARCHAR(255)    NOT NULL,
+    written_at         TIMESTAMP       NOT NULL,
+    replaced_at        TIMESTAMP       NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (review_id) REFERENCES review(review_id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
@@ -0,0 +1,29 @@
+-- Add migration script here
+
+-- One review per store per nomer
+-- Where a nomer already reviewed a store more than once, their latest
+-- review is kept, and the earlier ones become revisions of it so that
+-- moderators can still see them.
+INSERT INTO review_revision (review_id, score, comment, written_at)
+SELECT latest.review_id, older.score, older.comment, COALESCE(older.updated_at, older.created_at)
+FROM review AS older
+JOIN (
+    SELECT nomer_id, store_id, MAX(review_id) AS review_id
+    FROM review
+    WHERE nomer_id IS NOT NULL
+    GROUP BY nomer_id, store_id
+) AS latest
+    ON latest.nomer_id = older.nomer_id
+    AND latest.store_id = older.store_id
+    AND latest.review_id <> older.review_id
+ORDER BY older.review_id;
+
+DELETE older
+FROM review AS older
+JOIN review AS newer
+    ON newer.nomer_id = older.nomer_id
+    AND newer.store_id = older.store_id
+    AND newer.review_id > older.review_id;
+
+-- Anonymized reviews have no author, and so are not limited
+ALTER TABLE review ADD CONSTRAINT review_nomer_store UNIQUE (nomer_id, store_id);
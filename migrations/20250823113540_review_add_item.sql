@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- Reviews of a single item of a store
+-- A review without an item is of the store as a whole. Items cannot be
+-- deleted while reviewed, so that menu edits never take reviews with them;
+-- MySQL also forbids cascading into the generated `item_key` below.
+ALTER TABLE review ADD COLUMN item_id INTEGER NULL DEFAULT NULL;
+ALTER TABLE review ADD FOREIGN KEY (item_id) REFERENCES item(item_id)
+    ON DELETE RESTRICT
+    ON UPDATE RESTRICT;
+
+-- One review per store, and one per item of it, per nomer
+-- NULLs never clash in a unique key, so store reviews are keyed as item 0.
+ALTER TABLE review ADD COLUMN item_key INTEGER AS (COALESCE(item_id, 0)) STORED;
+ALTER TABLE review ADD CONSTRAINT review_nomer_store_item UNIQUE (nomer_id, store_id, item_key);
+ALTER TABLE review DROP INDEX review_nomer_store;
@@ -1,3 +1,5 @@
+use std::net::SocketAddr;
+
 use anyhow::{Context, Result};
 use axum::Router;
 use tokio::net::TcpListener;
@@ -32,9 +34,13 @@ pub(crate) async fn serve(config: Config, listener: TcpListener) -> Result<()> {
         .with_context(error_ctx!("Failed to create application"))?;
 
     // Serve application
-    axum::serve(listener, app)
-        .await
-        .with_context(error_ctx!("Failed to start server"))?;
+    // Connection info gives login throttling the client address
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .with_context(error_ctx!("Failed to start server"))?;
 
     Ok(())
 }
@@ -1,5 +1,8 @@
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use argon2::Params;
+use clap::{ArgAction, Parser, ValueEnum};
 
 use crate::error_ctx;
 
@@ -15,10 +18,187 @@ pub(crate) struct Config {
     #[arg(env = "PORT", required = true)]
     pub(super) port: u16,
 
-    /// The Base64-encoded secret key used for HMAC.
+    /// The secret key used for HMAC, registered under the key ID `default`.
+    /// Also verifies tokens issued without a key ID.
     /// Set by the `HMAC_SECRET` environment variable.
-    #[arg(env = "HMAC_SECRET", required = true)]
-    pub(super) hmac_secret: String,
+    #[arg(env = "HMAC_SECRET")]
+    pub(super) hmac_secret: Option<String>,
+
+    /// HMAC keyring, as a comma-separated list of `<kid>:<Base64 secret>`.
+    /// The first key signs new tokens; the rest are only used to verify.
+    /// Set by the `HMAC_KEYS` environment variable.
+    #[arg(env = "HMAC_KEYS")]
+    pub(super) hmac_keys: Option<String>,
+
+    /// Path to a PEM-encoded RSA or P-256 private key. When set, tokens are
+    /// signed with it instead of HMAC, and its public key is published at
+    /// `/api/.well-known/jwks.json`.
+    /// Set by the `TOKEN_SIGNING_KEY` environment variable.
+    #[arg(env = "TOKEN_SIGNING_KEY")]
+    pub(super) token_signing_key: Option<PathBuf>,
+
+    /// Lifetime of an access token, in seconds.
+    /// Set by the `ACCESS_TOKEN_LIFETIME` environment variable.
+    #[arg(env = "ACCESS_TOKEN_LIFETIME", default_value_t = 60 * 60)]
+    pub(super) access_token_lifetime: i64,
+
+    /// Lifetime of a refresh token, in seconds.
+    /// Set by the `REFRESH_TOKEN_LIFETIME` environment variable.
+    #[arg(env = "REFRESH_TOKEN_LIFETIME", default_value_t = 60 * 60 * 24 * 30)]
+    pub(super) refresh_token_lifetime: i64,
+
+    /// Lifetime of an email verification link, in seconds.
+    /// Set by the `EMAIL_VERIFICATION_LIFETIME` environment variable.
+    #[arg(env = "EMAIL_VERIFICATION_LIFETIME", default_value_t = 60 * 60 * 24)]
+    pub(super) email_verification_lifetime: i64,
+
+    /// Lifetime of a password reset link, in seconds.
+    /// Set by the `PASSWORD_RESET_LIFETIME` environment variable.
+    #[arg(env = "PASSWORD_RESET_LIFETIME", default_value_t = 60 * 60)]
+    pub(super) password_reset_lifetime: i64,
+
+    /// The `iss` claim of issued tokens, which verified tokens must match.
+    /// Set by the `TOKEN_ISSUER` environment variable.
+    #[arg(env = "TOKEN_ISSUER", required = true)]
+    pub(super) token_issuer: String,
+
+    /// The `aud` claim of issued tokens, which verified tokens must match.
+    /// Set by the `TOKEN_AUDIENCE` environment variable.
+    #[arg(env = "TOKEN_AUDIENCE", required = true)]
+    pub(super) token_audience: String,
+
+    /// Allowed clock skew, in seconds, when checking token timestamps.
+    /// Set by the `TOKEN_LEEWAY` environment variable.
+    #[arg(env = "TOKEN_LEEWAY", default_value_t = 60)]
+    pub(super) token_leeway: i64,
+
+    /// Memory used to hash a password with Argon2, in KiB. Existing hashes
+    /// are upgraded when their nomer next logs in.
+    /// Set by the `ARGON2_MEMORY_COST` environment variable.
+    #[arg(env = "ARGON2_MEMORY_COST", default_value_t = Params::DEFAULT_M_COST)]
+    pub(super) argon2_memory_cost: u32,
+
+    /// Number of Argon2 passes over memory when hashing a password.
+    /// Set by the `ARGON2_TIME_COST` environment variable.
+    #[arg(env = "ARGON2_TIME_COST", default_value_t = Params::DEFAULT_T_COST)]
+    pub(super) argon2_time_cost: u32,
+
+    /// Number of Argon2 lanes when hashing a password.
+    /// Set by the `ARGON2_PARALLELISM` environment variable.
+    #[arg(env = "ARGON2_PARALLELISM", default_value_t = Params::DEFAULT_P_COST)]
+    pub(super) argon2_parallelism: u32,
+
+    /// Lowest strength score, from 0 to 4, a new password must reach.
+    /// Scores are zxcvbn's: 3 is safely unguessable, 4 very unguessable.
+    /// Set by the `PASSWORD_MIN_STRENGTH` environment variable.
+    #[arg(
+        env = "PASSWORD_MIN_STRENGTH",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u8).range(0..=4)
+    )]
+    pub(super) password_min_strength: u8,
+
+    /// Path to a Have I Been Pwned SHA-1 hash list sorted by hash, or a
+    /// directory of its hash prefix range files. New passwords found in it
+    /// are rejected; it is searched on disk rather than loaded.
+    /// Set by the `BREACHED_PASSWORDS` environment variable.
+    #[arg(env = "BREACHED_PASSWORDS")]
+    pub(super) breached_passwords: Option<PathBuf>,
+
+    /// Where failed login attempts are counted: `memory` for a single
+    /// instance, or `database` to share lockouts between instances.
+    /// Set by the `LOGIN_THROTTLE_BACKEND` environment variable.
+    #[arg(env = "LOGIN_THROTTLE_BACKEND", value_enum, default_value_t = ThrottleBackend::Memory)]
+    pub(super) login_throttle_backend: ThrottleBackend,
+
+    /// Failed logins allowed per account before it is locked out.
+    /// Set by the `LOGIN_MAX_FAILURES_PER_ACCOUNT` environment variable.
+    #[arg(env = "LOGIN_MAX_FAILURES_PER_ACCOUNT", default_value_t = 5)]
+    pub(super) login_max_failures_per_account: u32,
+
+    /// Failed logins allowed per client address before it is locked out.
+    /// Set by the `LOGIN_MAX_FAILURES_PER_ADDRESS` environment variable.
+    #[arg(env = "LOGIN_MAX_FAILURES_PER_ADDRESS", default_value_t = 50)]
+    pub(super) login_max_failures_per_address: u32,
+
+    /// First lockout, in seconds, doubling with every further failure.
+    /// Set by the `LOGIN_LOCKOUT` environment variable.
+    #[arg(env = "LOGIN_LOCKOUT", default_value_t = 30)]
+    pub(super) login_lockout: i64,
+
+    /// Longest lockout, in seconds.
+    /// Set by the `LOGIN_MAX_LOCKOUT` environment variable.
+    #[arg(env = "LOGIN_MAX_LOCKOUT", default_value_t = 900)]
+    pub(super) login_max_lockout: i64,
+
+    /// Seconds after the last failure at which failed logins are forgotten.
+    /// Set by the `LOGIN_FAILURE_WINDOW` environment variable.
+    #[arg(env = "LOGIN_FAILURE_WINDOW", default_value_t = 3600)]
+    pub(super) login_failure_window: i64,
+
+    /// Whether to take the client address from `X-Forwarded-For`. Only
+    /// enable behind a reverse proxy that sets it.
+    /// Set by the `TRUST_X_FORWARDED_FOR` environment variable.
+    #[arg(env = "TRUST_X_FORWARDED_FOR", action = ArgAction::Set, default_value_t = false)]
+    pub(super) trust_x_forwarded_for: bool,
+
+    /// Comma-separated email domains allowed to sign up, such as
+    /// `u.nus.edu,nus.edu.sg`. Subdomains of a listed domain are allowed too.
+    /// If empty, any domain is allowed.
+    /// Set by the `ALLOWED_EMAIL_DOMAINS` environment variable.
+    #[arg(env = "ALLOWED_EMAIL_DOMAINS", default_value = "u.nus.edu,nus.edu.sg")]
+    pub(super) allowed_email_domains: String,
+
+    /// Base URL of the web app, used to build links in outgoing mail.
+    /// Set by the `APP_URL` environment variable.
+    #[arg(env = "APP_URL", default_value = "http://localhost:3000")]
+    pub(super) app_url: String,
+
+    /// How outgoing mail is delivered: `log` writes it to the application
+    /// log, `file` saves each message as an `.eml` file in `MAIL_DIR`.
+    /// Set by the `MAIL_BACKEND` environment variable.
+    #[arg(env = "MAIL_BACKEND", value_enum, default_value_t = MailBackend::Log)]
+    pub(super) mail_backend: MailBackend,
+
+    /// Directory outgoing mail is saved to by the `file` mail backend.
+    /// Set by the `MAIL_DIR` environment variable.
+    #[arg(env = "MAIL_DIR", default_value = "mail")]
+    pub(super) mail_dir: PathBuf,
+
+    /// How many imaginary reviews scoring the prior score each store starts
+    /// out with when ranking stores. The higher it is, the more reviews a
+    /// store needs before its own average counts for much.
+    /// Set by the `RANKING_PRIOR_WEIGHT` environment variable.
+    #[arg(env = "RANKING_PRIOR_WEIGHT", default_value_t = 10)]
+    pub(super) ranking_prior_weight: u32,
+
+    /// Score, from 1 to 5, of the imaginary reviews stores start out with
+    /// when ranking stores. If unset, the average review score across all
+    /// stores is used, whichever stores are being ranked.
+    /// Set by the `RANKING_PRIOR_SCORE` environment variable.
+    #[arg(env = "RANKING_PRIOR_SCORE", value_parser = parse_score)]
+    pub(super) ranking_prior_score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ThrottleBackend {
+    Memory,
+    Database,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum MailBackend {
+    Log,
+    File,
+}
+
+fn parse_score(value: &str) -> Result<f64, String> {
+    let score: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    if (1.0..=5.0).contains(&score) {
+        Ok(score)
+    } else {
+        Err("must be from 1 to 5".to_string())
+    }
 }
 
 #[tracing::instrument]
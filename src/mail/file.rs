@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{Mail, MailSender};
+use crate::error_ctx;
+
+/// Saves each mail as an `.eml` file in a directory instead of delivering
+/// it, so that it can be opened in a mail client or inspected by tests.
+pub(super) struct FileSender {
+    dir: PathBuf,
+}
+
+impl FileSender {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl MailSender for FileSender {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(error_ctx!("Failed to create {}", self.dir.display()))?;
+
+        // Timestamped names keep the directory listing in order
+        let now = Utc::now();
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            now.format("%Y%m%d%H%M%S%.3f"),
+            Uuid::new_v4()
+        ));
+        let contents = format!(
+            "Date: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            now.to_rfc2822(),
+            mail.to,
+            mail.subject,
+            mail.body
+        );
+
+        tokio::fs::write(&path, contents)
+            .await
+            .with_context(error_ctx!("Failed to write {}", path.display()))
+    }
+}
+
+/// Read back every mail saved to `dir`, oldest first.
+#[cfg(test)]
+pub(crate) fn read_all(dir: &std::path::Path) -> Result<Vec<Mail>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let contents = std::fs::read_to_string(path)?;
+            let (headers, body) = contents
+                .split_once("\r\n\r\n")
+                .context("Missing header separator")?;
+            let header = |name: &str| {
+                headers
+                    .split("\r\n")
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(str::to_string)
+                    .with_context(|| format!("Missing {name} header"))
+            };
+
+            Ok(Mail {
+                to: header("To: ")?,
+                subject: header("Subject: ")?,
+                body: body.to_string(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::info;
+
+use super::{Mail, MailSender};
+
+/// Writes mail to the application log instead of delivering it. Useful
+/// for local development.
+pub(super) struct LogSender;
+
+#[async_trait]
+impl MailSender for LogSender {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        info!(
+            "Mail to {} with subject {:?}:\n{}",
+            mail.to, mail.subject, mail.body
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+mod file;
+mod log;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::config::{Config, MailBackend};
+use file::FileSender;
+#[cfg(test)]
+pub(crate) use file::read_all;
+use log::LogSender;
+
+/// An email to a single recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers outgoing mail.
+#[async_trait]
+pub(crate) trait MailSender: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+/// Composes the mail we send to nomers and hands it to a [`MailSender`].
+#[derive(Clone)]
+pub(crate) struct Mailer {
+    sender: Arc<dyn MailSender>,
+    app_url: String,
+}
+
+impl Mailer {
+    pub fn from_config(config: &Config) -> Self {
+        let sender: Arc<dyn MailSender> = match config.mail_backend {
+            MailBackend::Log => Arc::new(LogSender),
+            MailBackend::File => Arc::new(FileSender::new(config.mail_dir.clone())),
+        };
+
+        Self::new(sender, &config.app_url)
+    }
+
+    pub fn new(sender: Arc<dyn MailSender>, app_url: &str) -> Self {
+        Self {
+            sender,
+            app_url: app_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Send `to` a link to verify their email address with `token`.
+    pub async fn send_verification(&self, to: &str, token: &str) -> Result<()> {
+        let link = format!("{}/verify-email?token={token}", self.app_url);
+        let mail = Mail {
+            to: to.to_string(),
+            subject: "Verify your NomNom email address".to_string(),
+            body: format!(
+                "Welcome to NomNom!\n\n\
+                 Please verify your email address by opening the link below:\n\n\
+                 {link}\n\n\
+                 If you did not sign up for NomNom, you can ignore this email.\n"
+            ),
+        };
+
+        self.sender.send(&mail).await
+    }
+
+    /// Send `to` a link to confirm changing the nomer's address to it with
+    /// `token`.
+    pub async fn send_email_change(&self, to: &str, token: &str) -> Result<()> {
+        let link = format!("{}/verify-email?token={token}", self.app_url);
+        let mail = Mail {
+            to: to.to_string(),
+            subject: "Confirm your new NomNom email address".to_string(),
+            body: format!(
+                "Someone asked to change the email address of a NomNom account\n\
+                 to this one. To confirm the change, open the link below:\n\n\
+                 {link}\n\n\
+                 If this was not you, you can ignore this email.\n"
+            ),
+        };
+
+        self.sender.send(&mail).await
+    }
+
+    /// Warn `to`, the current address of a nomer, that they asked to change
+    /// it to `new_email`.
+    pub async fn send_email_change_notice(&self, to: &str, new_email: &str) -> Result<()> {
+        let mail = Mail {
+            to: to.to_string(),
+            subject: "Your NomNom email address is being changed".to_string(),
+            body: format!(
+                "Someone asked to change the email address of your NomNom account\n\
+                 to {new_email}. The change takes effect once the new address is\n\
+                 confirmed.\n\n\
+                 If this was not you, change your password and ask for the\n\
+                 current address again on your profile to cancel the change.\n"
+            ),
+        };
+
+        self.sender.send(&mail).await
+    }
+
+    /// Send `to` a link to choose a new password with `token`.
+    pub async fn send_password_reset(&self, to: &str, token: &str) -> Result<()> {
+        let link = format!("{}/reset-password?token={token}", self.app_url);
+        let mail = Mail {
+            to: to.to_string(),
+            subject: "Reset your NomNom password".to_string(),
+            body: format!(
+                "Someone asked to reset the password of your NomNom account.\n\n\
+                 To choose a new password, open the link below:\n\n\
+                 {link}\n\n\
+                 If this was not you, you can ignore this email; your password\n\
+                 has not been changed.\n"
+            ),
+        };
+
+        self.sender.send(&mail).await
+    }
+}
+
+#[cfg(test)]
+impl Mailer {
+    /// A mailer that saves mail to a fresh temporary directory, returned
+    /// alongside it.
+    pub fn for_testing() -> (Self, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("nomnom-mail-{}", uuid::Uuid::new_v4()));
+        let mailer = Self::new(Arc::new(FileSender::new(dir.clone())), "http://app.test");
+        (mailer, dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_verification() {
+        let (mailer, dir) = Mailer::for_testing();
+        mailer
+            .send_verification("test@test.com", "abc.def.ghi")
+            .await
+            .unwrap();
+
+        let mail = read_all(&dir).unwrap();
+        assert_eq!(mail.len(), 1);
+        assert_eq!(mail[0].to, "test@test.com");
+        assert!(
+            mail[0]
+                .body
+                .contains("http://app.test/verify-email?token=abc.def.ghi")
+        );
+    }
+}
@@ -4,9 +4,13 @@
 mod app;
 mod config;
 mod macros;
+mod mail;
 mod models;
+mod passwords;
 mod routes;
 mod state;
+mod throttle;
+mod tokens;
 
 use anyhow::{Context, Result};
 use tokio::net::TcpListener;
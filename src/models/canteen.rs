@@ -1,7 +1,7 @@
 use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 
-use crate::models::Store;
+use crate::models::{Rating, Store};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,5 +11,7 @@ pub struct Canteen {
     pub latitude: BigDecimal,
     pub longitude: BigDecimal,
     pub image_url: String,
+    /// Of the reviews of all its stores.
+    pub rating: Rating,
     pub stores: Vec<Store>,
 }
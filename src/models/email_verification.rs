@@ -0,0 +1,373 @@
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, Executor, MySql, MySqlPool};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::tokens::{TokenType, Tokens};
+
+/// Email verification tokens, as recorded in the `email_verification` table.
+///
+/// The signed token carries the nomer, the address and the ID of the stored
+/// token (as `jti`); the stored token only records whether it was used.
+pub struct EmailVerification;
+
+impl EmailVerification {
+    /// Record a new verification token for `email` and return it signed.
+    ///
+    /// Takes any executor, so that the token can be recorded in the same
+    /// transaction as the change it confirms.
+    pub async fn issue(
+        db: impl Executor<'_, Database = MySql>,
+        tokens: &Tokens,
+        nomer_id: i64,
+        email: &str,
+    ) -> Result<String, (StatusCode, &'static str)> {
+        let token_id = Uuid::new_v4().to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO email_verification (token_id, nomer_id, email, issued_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+            token_id,
+            nomer_id,
+            email,
+            Utc::now().naive_utc()
+        )
+        .execute(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to store email verification token: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        let claim = tokens.email_claim(nomer_id, email.to_string(), token_id);
+        tokens.sign(&claim).ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to generate verification token",
+        ))
+    }
+
+    /// Whether `nomer_id` was sent an unused token for `email` less than
+    /// `within` seconds ago.
+    pub async fn recently_issued(
+        db: &MySqlPool,
+        nomer_id: i64,
+        email: &str,
+        within: i64,
+    ) -> Result<bool, (StatusCode, &'static str)> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*)
+            FROM email_verification
+            WHERE nomer_id = ? AND email = ? AND used_at IS NULL AND issued_at > ?
+            "#,
+            nomer_id,
+            email,
+            (Utc::now() - Duration::seconds(within)).naive_utc()
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up email verification tokens: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(count > 0)
+    }
+
+    /// Use up the verification token `token`, marking the address it was
+    /// sent to as verified. Returns the ID of the verified nomer.
+    ///
+    /// If the token was sent to the address the nomer asked to change to,
+    /// that address replaces their current one.
+    pub async fn confirm(
+        db: &MySqlPool,
+        tokens: &Tokens,
+        token: &str,
+    ) -> Result<i64, (StatusCode, &'static str)> {
+        let claim = tokens.verify_email(token)?;
+        claim.validate(Utc::now().timestamp(), tokens.leeway())?;
+        let Ok(nomer_id) = claim.sub.parse::<i64>() else {
+            return Err((StatusCode::BAD_REQUEST, "Invalid verification token"));
+        };
+
+        let db_error = |e: Error| {
+            error!("Database error while verifying email: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        };
+        let now = Utc::now().naive_utc();
+        let mut tx = db.begin().await.map_err(db_error)?;
+
+        // Only one request may use the token
+        let used = sqlx::query!(
+            r#"
+            UPDATE email_verification
+            SET used_at = ?
+            WHERE token_id = ? AND nomer_id = ? AND email = ? AND used_at IS NULL
+            "#,
+            now,
+            claim.jti,
+            nomer_id,
+            claim.email
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        if used.rows_affected() == 0 {
+            return Err((StatusCode::BAD_REQUEST, "Verification token already used"));
+        }
+
+        // The link is void if the nomer has since changed address
+        let mut verified = sqlx::query!(
+            r#"
+            UPDATE nomer
+            SET email_verified_at = ?
+            WHERE nomer_id = ? AND email = ?
+            "#,
+            now,
+            nomer_id,
+            claim.email
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .rows_affected();
+
+        // ...or if they have since asked for yet another address
+        if verified == 0 {
+            verified = sqlx::query!(
+                r#"
+                UPDATE nomer
+                SET email = pending_email, pending_email = NULL, email_verified_at = ?
+                WHERE nomer_id = ? AND pending_email = ?
+                "#,
+                now,
+                nomer_id,
+                claim.email
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| match e {
+                // Someone else took the address while the link was in the mail
+                Error::Database(db_err) if db_err.is_unique_violation() => {
+                    (StatusCode::CONFLICT, "Email already exists")
+                }
+                e => db_error(e),
+            })?
+            .rows_affected();
+        }
+        if verified == 0 {
+            return Err((StatusCode::BAD_REQUEST, "Invalid verification token"));
+        }
+
+        tx.commit().await.map_err(db_error)?;
+
+        Ok(nomer_id)
+    }
+}
+
+/// Claim proving that a nomer received mail at an address.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailClaim {
+    pub iss: String,
+    pub aud: String,
+    pub typ: TokenType,
+    pub sub: String,
+    pub email: String,
+    pub exp: i64,
+    pub iat: i64,
+    /// ID of the stored verification token.
+    pub jti: String,
+}
+
+impl EmailClaim {
+    /// Check the claim has not expired, allowing `leeway` seconds of skew.
+    pub fn validate(&self, now: i64, leeway: i64) -> Result<(), (StatusCode, &'static str)> {
+        if now >= self.exp + leeway {
+            return Err((StatusCode::BAD_REQUEST, "Verification token expired"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn email(db: &MySqlPool) -> (String, Option<String>) {
+        let row = sqlx::query!("SELECT email, pending_email FROM nomer WHERE nomer_id = 1")
+            .fetch_one(db)
+            .await
+            .unwrap();
+        (row.email, row.pending_email)
+    }
+
+    async fn is_verified(db: &MySqlPool) -> bool {
+        sqlx::query_scalar!("SELECT email_verified_at FROM nomer WHERE nomer_id = 1")
+            .fetch_one(db)
+            .await
+            .unwrap()
+            .is_some()
+    }
+
+    #[test]
+    fn test_validate() {
+        let claim = Tokens::for_testing().email_claim(1, "a@test.com".to_string(), "id".into());
+        assert!(claim.validate(claim.iat, 0).is_ok());
+        assert_eq!(
+            claim.validate(claim.exp, 0).unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Verification token expired")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_issue_and_confirm(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+        assert!(!is_verified(&db).await);
+
+        let token = EmailVerification::issue(&db, &tokens, 1, "test1@test.com")
+            .await
+            .unwrap();
+        assert_eq!(
+            EmailVerification::confirm(&db, &tokens, &token).await,
+            Ok(1)
+        );
+        assert!(is_verified(&db).await);
+
+        // Tokens are single-use
+        assert_eq!(
+            EmailVerification::confirm(&db, &tokens, &token)
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Verification token already used")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_recently_issued(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+        let recently_issued = |email| EmailVerification::recently_issued(&db, 1, email, 60);
+        assert_eq!(recently_issued("test1@test.com").await, Ok(false));
+
+        let token = EmailVerification::issue(&db, &tokens, 1, "test1@test.com")
+            .await
+            .unwrap();
+        assert_eq!(recently_issued("test1@test.com").await, Ok(true));
+        assert_eq!(recently_issued("new@test.com").await, Ok(false));
+
+        // Used tokens do not hold up a new one
+        EmailVerification::confirm(&db, &tokens, &token)
+            .await
+            .unwrap();
+        assert_eq!(recently_issued("test1@test.com").await, Ok(false));
+    }
+
+    #[sqlx::test]
+    async fn test_confirm_other_address(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+
+        // Sent to an address the nomer no longer has
+        let token = EmailVerification::issue(&db, &tokens, 1, "old@test.com")
+            .await
+            .unwrap();
+        assert_eq!(
+            EmailVerification::confirm(&db, &tokens, &token)
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Invalid verification token")
+        );
+        assert!(!is_verified(&db).await);
+    }
+
+    #[sqlx::test]
+    async fn test_confirm_pending_email(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+        sqlx::query!("UPDATE nomer SET pending_email = 'new@test.com' WHERE nomer_id = 1")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let token = EmailVerification::issue(&db, &tokens, 1, "new@test.com")
+            .await
+            .unwrap();
+        assert_eq!(
+            EmailVerification::confirm(&db, &tokens, &token).await,
+            Ok(1)
+        );
+        assert_eq!(email(&db).await, ("new@test.com".to_string(), None));
+        assert!(is_verified(&db).await);
+    }
+
+    #[sqlx::test]
+    async fn test_confirm_pending_email_taken(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 2",
+            "new@test.com",
+            "test_hash_2"
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        sqlx::query!("UPDATE nomer SET pending_email = 'new@test.com' WHERE nomer_id = 1")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let token = EmailVerification::issue(&db, &tokens, 1, "new@test.com")
+            .await
+            .unwrap();
+        assert_eq!(
+            EmailVerification::confirm(&db, &tokens, &token)
+                .await
+                .unwrap_err(),
+            (StatusCode::CONFLICT, "Email already exists")
+        );
+        assert_eq!(
+            email(&db).await,
+            (
+                "test1@test.com".to_string(),
+                Some("new@test.com".to_string())
+            )
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_confirm_rejects_session_tokens(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+
+        let access_token = tokens.sign(&tokens.claim("1".to_string(), true)).unwrap();
+        assert_eq!(
+            EmailVerification::confirm(&db, &tokens, &access_token)
+                .await
+                .unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token")
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 
+use crate::models::Rating;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Item {
@@ -10,4 +12,5 @@ pub struct Item {
     pub is_available: bool,
     pub information: String,
     pub image_url: String,
+    pub rating: Rating,
 }
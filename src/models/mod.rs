@@ -1,11 +1,19 @@
 mod canteen;
+mod email_verification;
 mod item;
 mod nomer;
+mod password_reset;
+mod rating;
+mod refresh_token;
 mod review;
 mod store;
 
 pub use canteen::Canteen;
+pub use email_verification::{EmailClaim, EmailVerification};
 pub use item::Item;
-pub use nomer::{Nomer, NomerClaim};
+pub use nomer::{ClaimSubject, Nomer, NomerClaim};
+pub use password_reset::PasswordReset;
+pub use rating::Rating;
+pub use refresh_token::RefreshToken;
 pub use review::Review;
 pub use store::Store;
@@ -1,16 +1,17 @@
 use axum::{
     extract::FromRequestParts,
-    http::{StatusCode, request::Parts},
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION, request::Parts},
 };
-use chrono::Utc;
-use hmac::Hmac;
-use jwt::{SignWithKey, VerifyWithKey};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
-use sqlx::Error;
+use sqlx::{Error, MySqlPool};
 use tracing::error;
 
-use crate::state::AppState;
+use super::RefreshToken;
+use crate::{
+    state::AppState,
+    tokens::{TokenType, Tokens},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -19,33 +20,96 @@ pub struct Nomer {
     pub display_name: String,
     pub email: String,
     pub password_hash: String,
+    /// Bumped to invalidate every access token issued before.
+    pub token_generation: i64,
+    /// When the nomer proved they own `email`, if they have.
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Address the nomer asked to change to, awaiting verification.
+    pub pending_email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
 }
 
 impl Nomer {
-    pub fn make_access_token(&self, key: &Hmac<Sha256>) -> Option<String> {
-        NomerClaim::make(self.email.clone(), 60 * 60, true)
-            .sign_with_key(key)
-            .ok()
+    pub fn is_verified(&self) -> bool {
+        self.email_verified_at.is_some()
     }
 
-    pub fn make_refresh_token(&self, key: &Hmac<Sha256>) -> Option<String> {
-        NomerClaim::make(self.email.clone(), 60 * 60 * 24 * 30, false)
-            .sign_with_key(key)
-            .ok()
+    pub fn make_access_token(&self, tokens: &Tokens) -> Option<String> {
+        let mut claim = tokens.claim(self.id.to_string(), true);
+        claim.generation = self.token_generation;
+        tokens.sign(&claim)
     }
-}
 
-impl FromRequestParts<AppState> for Nomer {
-    type Rejection = (StatusCode, &'static str);
+    /// Sign a refresh token for the stored refresh token `token_id`.
+    pub fn make_refresh_token(&self, tokens: &Tokens, token_id: String) -> Option<String> {
+        let mut claim = tokens.claim(self.id.to_string(), false);
+        claim.jti = token_id;
+        tokens.sign(&claim)
+    }
 
-    async fn from_request_parts(
-        parts: &mut Parts,
-        state: &AppState,
-    ) -> Result<Self, Self::Rejection> {
-        // Extract claim
-        let claim = NomerClaim::from_request_parts(parts, state).await?;
+    /// Start a new session, returning its access token and the refresh
+    /// token of a new family.
+    pub async fn start_session(
+        &self,
+        db: &MySqlPool,
+        tokens: &Tokens,
+    ) -> Result<(String, String), (StatusCode, &'static str)> {
+        let Some(access_token) = self.make_access_token(tokens) else {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create access token",
+            ));
+        };
+        let token_id = RefreshToken::issue(db, self.id, None, tokens.refresh_lifetime()).await?;
+        let Some(refresh_token) = self.make_refresh_token(tokens, token_id) else {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create refresh token",
+            ));
+        };
+
+        Ok((access_token, refresh_token))
+    }
+
+    /// Fetch the nomer with the given ID, if any.
+    pub async fn fetch_by_id(
+        db: &MySqlPool,
+        nomer_id: i64,
+    ) -> Result<Option<Self>, (StatusCode, &'static str)> {
+        sqlx::query_as!(
+            Nomer,
+            r#"
+            SELECT
+                nomer_id as id,
+                display_name,
+                email,
+                password_hash,
+                token_generation,
+                email_verified_at,
+                pending_email,
+                created_at,
+                bio,
+                avatar_url
+            FROM nomer
+            WHERE nomer_id = ?
+            "#,
+            nomer_id
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(|e| {
+            error!("Database error while fetching nomer: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })
+    }
 
-        // Find user by email
+    /// Fetch the nomer with the given email, if any.
+    pub async fn fetch_by_email(
+        db: &MySqlPool,
+        email: &str,
+    ) -> Result<Option<Self>, (StatusCode, &'static str)> {
         sqlx::query_as!(
             Nomer,
             r#"
@@ -53,46 +117,160 @@ impl FromRequestParts<AppState> for Nomer {
                 nomer_id as id,
                 display_name,
                 email,
-                password_hash
+                password_hash,
+                token_generation,
+                email_verified_at,
+                pending_email,
+                created_at,
+                bio,
+                avatar_url
             FROM nomer
             WHERE email = ?
             "#,
-            claim.sub
+            email
         )
-        .fetch_one(state.db())
+        .fetch_optional(db)
         .await
         .map_err(|e| {
-            if let Error::RowNotFound = e {
-                // Nomer not found for the given email, even though the claim is valid
-                // This could happen if the user was deleted after the token was issued
-                // Log the error for debugging purposes
-                error!("Nomer not found for email: {}", claim.sub);
-                (StatusCode::UNAUTHORIZED, "Nomer not found")
-            } else {
-                error!("Database error while fetching nomer: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
-            }
+            error!("Database error while fetching nomer: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })
+    }
+
+    /// Log the nomer out of every device.
+    ///
+    /// Outstanding access tokens are invalidated by bumping the token
+    /// generation, and every refresh token is revoked.
+    pub async fn revoke_all_sessions(
+        db: &MySqlPool,
+        nomer_id: i64,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        let db_error = |e: Error| {
+            error!("Database error while revoking sessions: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        };
+
+        let mut tx = db.begin().await.map_err(db_error)?;
+
+        sqlx::query!(
+            r#"
+            UPDATE nomer
+            SET token_generation = token_generation + 1
+            WHERE nomer_id = ?
+            "#,
+            nomer_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        sqlx::query!(
+            r#"
+            UPDATE refresh_token
+            SET revoked_at = ?
+            WHERE nomer_id = ? AND revoked_at IS NULL
+            "#,
+            Utc::now().naive_utc(),
+            nomer_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)
+    }
+}
+
+impl FromRequestParts<AppState> for Nomer {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        // Extract claim
+        let claim = NomerClaim::from_request_parts(parts, state).await?;
+
+        // Find user by ID, or by email for tokens issued before IDs were used
+        let nomer = match claim.subject() {
+            ClaimSubject::Id(id) => Nomer::fetch_by_id(state.db(), id).await?,
+            ClaimSubject::Email(email) => Nomer::fetch_by_email(state.db(), email).await?,
+        };
+
+        nomer.ok_or_else(|| {
+            // Nomer not found for the given subject, even though the claim is valid
+            // This could happen if the user was deleted after the token was issued
+            // Log the error for debugging purposes
+            error!("Nomer not found for subject: {}", claim.sub);
+            (StatusCode::UNAUTHORIZED, "Nomer not found")
         })
     }
 }
 
+/// The nomer a claim was issued to.
+pub enum ClaimSubject<'a> {
+    Id(i64),
+    /// Tokens issued before subjects switched to IDs carry the email instead.
+    Email(&'a str),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NomerClaim {
+    /// Empty on tokens issued before issuers and audiences were checked.
+    #[serde(default)]
+    pub iss: String,
+    #[serde(default)]
+    pub aud: String,
+    /// `None` on tokens issued before issuers and audiences were checked.
+    #[serde(default)]
+    pub typ: Option<TokenType>,
     pub sub: String,
     pub exp: i64,
     pub iat: i64,
     pub acc: bool,
+    /// Token generation of the nomer at the time of issue.
+    #[serde(default, rename = "gen")]
+    pub generation: i64,
+    /// Unique token ID; for refresh tokens, the ID of the stored token.
+    /// Empty on access tokens issued before every token had one.
+    #[serde(default)]
+    pub jti: String,
 }
 
 impl NomerClaim {
-    pub fn make(subject: String, duration: i64, is_access: bool) -> Self {
-        let now = Utc::now().timestamp();
-        NomerClaim {
-            sub: subject,
-            exp: now + duration,
-            iat: now,
-            acc: is_access,
+    /// Check the claim's timestamps against `now`, allowing `leeway` seconds
+    /// of clock skew in either direction.
+    pub fn validate(&self, now: i64, leeway: i64) -> Result<(), (StatusCode, &'static str)> {
+        if now >= self.exp + leeway {
+            return Err((StatusCode::UNAUTHORIZED, "Token expired"));
+        }
+
+        if self.iat - leeway > now {
+            return Err((StatusCode::UNAUTHORIZED, "Token issued in the future"));
+        }
+
+        Ok(())
+    }
+
+    /// Like [`NomerClaim::validate`], but also rejects refresh tokens.
+    pub fn validate_access(&self, now: i64, leeway: i64) -> Result<(), (StatusCode, &'static str)> {
+        self.validate(now, leeway)?;
+
+        if !self.acc {
+            return Err((StatusCode::UNAUTHORIZED, "Access token required"));
+        }
+
+        Ok(())
+    }
+
+    /// Interpret `sub`, which is a nomer ID unless the token predates those.
+    ///
+    /// Emails always contain an `@`, so they never parse as an ID.
+    pub fn subject(&self) -> ClaimSubject<'_> {
+        match self.sub.parse() {
+            Ok(id) => ClaimSubject::Id(id),
+            Err(_) => ClaimSubject::Email(&self.sub),
         }
     }
 }
@@ -104,21 +282,277 @@ impl FromRequestParts<AppState> for NomerClaim {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let Some(api_key_header) = parts.headers.get("X-Api-Key") else {
-            // Missing X-Api-Key header
-            return Err((StatusCode::UNAUTHORIZED, "Missing X-Api-Key header"));
-        };
+        let api_key = extract_token(&parts.headers)?;
+
+        // Well-formed header, but the token must be genuine and ours
+        let claim = state.tokens().verify(api_key)?;
+
+        // Only unexpired access tokens may be used on protected routes
+        claim.validate_access(Utc::now().timestamp(), state.tokens().leeway())?;
+
+        // Reject tokens issued before the nomer last logged out everywhere
+        let generation = match claim.subject() {
+            ClaimSubject::Id(id) => {
+                sqlx::query_scalar!(
+                    r#"
+                    SELECT token_generation
+                    FROM nomer
+                    WHERE nomer_id = ?
+                    "#,
+                    id
+                )
+                .fetch_optional(state.db())
+                .await
+            }
+            ClaimSubject::Email(email) => {
+                sqlx::query_scalar!(
+                    r#"
+                    SELECT token_generation
+                    FROM nomer
+                    WHERE email = ?
+                    "#,
+                    email
+                )
+                .fetch_optional(state.db())
+                .await
+            }
+        }
+        .map_err(|e| {
+            error!("Database error while fetching token generation: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        match generation {
+            Some(generation) if i64::from(generation) == claim.generation => Ok(claim),
+            Some(_) => Err((StatusCode::UNAUTHORIZED, "Token revoked")),
+            None => Err((StatusCode::UNAUTHORIZED, "Nomer not found")),
+        }
+    }
+}
 
-        let Some(api_key) = api_key_header.to_str().ok() else {
-            // Invalid (encoding) X-Api-Key header
-            return Err((StatusCode::UNAUTHORIZED, "Invalid X-Api-Key header"));
+/// Extract the token from the request headers.
+///
+/// `Authorization: Bearer <token>` takes precedence; `X-Api-Key` is only
+/// consulted when there is no `Authorization` header at all.
+fn extract_token(headers: &HeaderMap) -> Result<&str, (StatusCode, &'static str)> {
+    if let Some(authorization) = headers.get(AUTHORIZATION) {
+        let Ok(authorization) = authorization.to_str() else {
+            // Invalid (encoding) Authorization header
+            return Err((StatusCode::UNAUTHORIZED, "Invalid Authorization header"));
         };
 
-        let Ok(claim): Result<NomerClaim, _> = api_key.verify_with_key(state.hmac()) else {
-            // Valid X-Api-Key header, but invalid API key
-            return Err((StatusCode::UNAUTHORIZED, "Invalid API key"));
+        // The scheme is case-insensitive (RFC 7235)
+        let (scheme, token) = authorization
+            .trim()
+            .split_once(' ')
+            .unwrap_or((authorization.trim(), ""));
+        if !scheme.eq_ignore_ascii_case("Bearer") {
+            return Err((StatusCode::UNAUTHORIZED, "Unsupported authorization scheme"));
+        }
+
+        let token = token.trim();
+        if token.is_empty() {
+            return Err((StatusCode::UNAUTHORIZED, "Missing bearer token"));
+        }
+
+        return Ok(token);
+    }
+
+    let Some(api_key_header) = headers.get("X-Api-Key") else {
+        // Neither header present
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Missing Authorization or X-Api-Key header",
+        ));
+    };
+
+    let Ok(api_key) = api_key_header.to_str() else {
+        // Invalid (encoding) X-Api-Key header
+        return Err((StatusCode::UNAUTHORIZED, "Invalid X-Api-Key header"));
+    };
+
+    Ok(api_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_claim_subject() {
+        let tokens = Tokens::for_testing();
+        let claim = tokens.claim("42".to_string(), true);
+        assert!(matches!(claim.subject(), ClaimSubject::Id(42)));
+
+        let claim = tokens.claim("test@test.com".to_string(), true);
+        assert!(matches!(
+            claim.subject(),
+            ClaimSubject::Email("test@test.com")
+        ));
+    }
+
+    #[test]
+    fn test_tokens_use_id_as_subject() {
+        let tokens = Tokens::for_testing();
+        let nomer = Nomer {
+            id: 7,
+            display_name: "Test".to_string(),
+            email: "test@test.com".to_string(),
+            password_hash: "test_hash".to_string(),
+            token_generation: 0,
+            email_verified_at: None,
+            pending_email: None,
+            created_at: Utc::now(),
+            bio: None,
+            avatar_url: None,
         };
 
-        Ok(claim)
+        let token = nomer.make_access_token(&tokens).unwrap();
+        let claim = tokens.verify(&token).unwrap();
+        assert_eq!(claim.sub, "7");
+    }
+
+    fn claim_at(iat: i64, exp: i64, acc: bool) -> NomerClaim {
+        NomerClaim {
+            exp,
+            iat,
+            acc,
+            ..Tokens::for_testing().claim("1".to_string(), acc)
+        }
+    }
+
+    #[test]
+    fn test_validate_claim() {
+        let claim = claim_at(1000, 2000, true);
+        assert!(claim.validate(1500, 0).is_ok());
+        assert!(claim.validate_access(1500, 0).is_ok());
+    }
+
+    #[test]
+    fn test_validate_claim_expired() {
+        let claim = claim_at(1000, 2000, true);
+        assert_eq!(
+            claim.validate(2000, 0),
+            Err((StatusCode::UNAUTHORIZED, "Token expired"))
+        );
+        assert_eq!(
+            claim.validate(2100, 60),
+            Err((StatusCode::UNAUTHORIZED, "Token expired"))
+        );
+
+        // Within leeway
+        assert!(claim.validate(2059, 60).is_ok());
+    }
+
+    #[test]
+    fn test_validate_claim_issued_in_future() {
+        let claim = claim_at(1000, 2000, true);
+        assert_eq!(
+            claim.validate(900, 0),
+            Err((StatusCode::UNAUTHORIZED, "Token issued in the future"))
+        );
+
+        // Within leeway
+        assert!(claim.validate(940, 60).is_ok());
+    }
+
+    #[test]
+    fn test_validate_access_rejects_refresh_token() {
+        let claim = claim_at(1000, 2000, false);
+        assert!(claim.validate(1500, 0).is_ok());
+        assert_eq!(
+            claim.validate_access(1500, 0),
+            Err((StatusCode::UNAUTHORIZED, "Access token required"))
+        );
+
+        // Expiry is reported first
+        assert_eq!(
+            claim.validate_access(2500, 0),
+            Err((StatusCode::UNAUTHORIZED, "Token expired"))
+        );
+    }
+
+    fn headers(pairs: &[(&'static str, &[u8])]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_bytes(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_extract_token_bearer() {
+        let h = headers(&[("authorization", b"Bearer abc.def.ghi")]);
+        assert_eq!(extract_token(&h), Ok("abc.def.ghi"));
+
+        let h = headers(&[("authorization", b"bearer abc.def.ghi")]);
+        assert_eq!(extract_token(&h), Ok("abc.def.ghi"));
+    }
+
+    #[test]
+    fn test_extract_token_api_key() {
+        let h = headers(&[("x-api-key", b"abc.def.ghi")]);
+        assert_eq!(extract_token(&h), Ok("abc.def.ghi"));
+    }
+
+    #[test]
+    fn test_extract_token_precedence() {
+        let h = headers(&[
+            ("authorization", b"Bearer from.bearer.header"),
+            ("x-api-key", b"from.api.key"),
+        ]);
+        assert_eq!(extract_token(&h), Ok("from.bearer.header"));
+
+        // A broken Authorization header is not rescued by X-Api-Key
+        let h = headers(&[
+            ("authorization", b"Basic dXNlcjpwYXNz"),
+            ("x-api-key", b"k"),
+        ]);
+        assert_eq!(
+            extract_token(&h),
+            Err((StatusCode::UNAUTHORIZED, "Unsupported authorization scheme"))
+        );
+    }
+
+    #[test]
+    fn test_extract_token_rejections() {
+        assert_eq!(
+            extract_token(&HeaderMap::new()),
+            Err((
+                StatusCode::UNAUTHORIZED,
+                "Missing Authorization or X-Api-Key header"
+            ))
+        );
+
+        let h = headers(&[("authorization", b"Bearer")]);
+        assert_eq!(
+            extract_token(&h),
+            Err((StatusCode::UNAUTHORIZED, "Missing bearer token"))
+        );
+
+        let h = headers(&[("authorization", b"Bearer   ")]);
+        assert_eq!(
+            extract_token(&h),
+            Err((StatusCode::UNAUTHORIZED, "Missing bearer token"))
+        );
+
+        let h = headers(&[("authorization", b"Token abc")]);
+        assert_eq!(
+            extract_token(&h),
+            Err((StatusCode::UNAUTHORIZED, "Unsupported authorization scheme"))
+        );
+
+        let h = headers(&[("authorization", b"Bearer \xff")]);
+        assert_eq!(
+            extract_token(&h),
+            Err((StatusCode::UNAUTHORIZED, "Invalid Authorization header"))
+        );
+
+        let h = headers(&[("x-api-key", b"\xff")]);
+        assert_eq!(
+            extract_token(&h),
+            Err((StatusCode::UNAUTHORIZED, "Invalid X-Api-Key header"))
+        );
     }
 }
@@ -0,0 +1,230 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Error, MySqlPool};
+use tracing::error;
+
+/// Password reset tokens, as recorded in the `password_reset` table.
+///
+/// Tokens are random rather than signed, and only their hash is stored.
+pub struct PasswordReset;
+
+impl PasswordReset {
+    /// Record a new reset token for `nomer_id` and return it.
+    pub async fn issue(
+        db: &MySqlPool,
+        nomer_id: i64,
+        lifetime: i64,
+    ) -> Result<String, (StatusCode, &'static str)> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        let issued_at = Utc::now();
+        let expires_at = issued_at + Duration::seconds(lifetime);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO password_reset (token_hash, nomer_id, issued_at, expires_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+            hash_token(&token),
+            nomer_id,
+            issued_at.naive_utc(),
+            expires_at.naive_utc()
+        )
+        .execute(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to store password reset token: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(token)
+    }
+
+    /// Whether `nomer_id` has a token that can still be used and was issued
+    /// less than `within` seconds ago.
+    pub async fn recently_issued(
+        db: &MySqlPool,
+        nomer_id: i64,
+        within: i64,
+    ) -> Result<bool, (StatusCode, &'static str)> {
+        let now = Utc::now();
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*)
+            FROM password_reset
+            WHERE nomer_id = ? AND used_at IS NULL AND expires_at > ? AND issued_at > ?
+            "#,
+            nomer_id,
+            now.naive_utc(),
+            (now - Duration::seconds(within)).naive_utc()
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up password reset tokens: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(count > 0)
+    }
+
+    /// The ID of the nomer `token` was issued to, if it can still be used.
+    /// Unlike [`PasswordReset::confirm`], this does not use it up.
+    pub async fn nomer_id(
+        db: &MySqlPool,
+        token: &str,
+    ) -> Result<Option<i64>, (StatusCode, &'static str)> {
+        let nomer_id = sqlx::query_scalar!(
+            r#"
+            SELECT nomer_id
+            FROM password_reset
+            WHERE token_hash = ? AND used_at IS NULL AND expires_at > ?
+            "#,
+            hash_token(token),
+            Utc::now().naive_utc()
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up password reset token: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(nomer_id.map(i64::from))
+    }
+
+    /// Use up `token` to set the password hash of the nomer it was issued
+    /// to. Returns the ID of that nomer.
+    ///
+    /// Every other outstanding token for the nomer is used up as well.
+    pub async fn confirm(
+        db: &MySqlPool,
+        token: &str,
+        password_hash: &str,
+    ) -> Result<i64, (StatusCode, &'static str)> {
+        let db_error = |e: Error| {
+            error!("Database error while resetting password: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        };
+        let now = Utc::now().naive_utc();
+        let mut tx = db.begin().await.map_err(db_error)?;
+
+        // Lock the token so that it can only be used once
+        let nomer_id = sqlx::query_scalar!(
+            r#"
+            SELECT nomer_id
+            FROM password_reset
+            WHERE token_hash = ? AND used_at IS NULL AND expires_at > ?
+            FOR UPDATE
+            "#,
+            hash_token(token),
+            now
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        let Some(nomer_id) = nomer_id else {
+            return Err((StatusCode::BAD_REQUEST, "Invalid or expired reset token"));
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE password_reset
+            SET used_at = ?
+            WHERE nomer_id = ? AND used_at IS NULL
+            "#,
+            now,
+            nomer_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        sqlx::query!(
+            "UPDATE nomer SET password_hash = ? WHERE nomer_id = ?",
+            password_hash,
+            nomer_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+
+        Ok(i64::from(nomer_id))
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn password_hash(db: &MySqlPool) -> String {
+        sqlx::query_scalar!("SELECT password_hash FROM nomer WHERE nomer_id = 1")
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_hash_token() {
+        let hash = hash_token("token");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, hash_token("token"));
+        assert_ne!(hash, hash_token("other"));
+    }
+
+    #[sqlx::test]
+    async fn test_issue_and_confirm(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token = PasswordReset::issue(&db, 1, 60).await.unwrap();
+        let other = PasswordReset::issue(&db, 1, 60).await.unwrap();
+        assert_ne!(token, other);
+
+        assert_eq!(PasswordReset::confirm(&db, &token, "new_hash").await, Ok(1));
+        assert_eq!(password_hash(&db).await, "new_hash");
+
+        // Neither the used token nor any other outstanding one works again
+        for token in [token, other] {
+            assert_eq!(
+                PasswordReset::confirm(&db, &token, "newer_hash").await,
+                Err((StatusCode::BAD_REQUEST, "Invalid or expired reset token"))
+            );
+        }
+        assert_eq!(password_hash(&db).await, "new_hash");
+    }
+
+    #[sqlx::test]
+    async fn test_confirm_expired(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token = PasswordReset::issue(&db, 1, -60).await.unwrap();
+        assert_eq!(
+            PasswordReset::confirm(&db, &token, "new_hash").await,
+            Err((StatusCode::BAD_REQUEST, "Invalid or expired reset token"))
+        );
+        assert_eq!(password_hash(&db).await, "test_hash_1");
+    }
+}
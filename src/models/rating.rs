@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+/// Summary of the review scores given to a store, item or canteen
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rating {
+    /// `None` until the first review.
+    pub average_score: Option<f64>,
+    pub review_count: i64,
+    /// Number of reviews scoring 1 to 5, in that order.
+    pub histogram: [i64; 5],
+}
+
+impl Rating {
+    pub fn from_histogram(histogram: [i64; 5]) -> Self {
+        let mut rating = Self {
+            histogram,
+            ..Self::default()
+        };
+        rating.update_summary();
+        rating
+    }
+
+    /// Record `count` more reviews scoring `score`. Scores outside 1 to 5 are
+    /// ignored.
+    pub fn add(&mut self, score: i64, count: i64) {
+        let bucket = usize::try_from(score - 1)
+            .ok()
+            .and_then(|index| self.histogram.get_mut(index));
+        if let Some(bucket) = bucket {
+            *bucket += count;
+            self.update_summary();
+        }
+    }
+
+    /// Sum of the scores of all reviews.
+    pub fn total(&self) -> i64 {
+        self.histogram
+            .iter()
+            .zip(1..)
+            .map(|(count, score)| count * score)
+            .sum()
+    }
+
+    /// Bayesian average score, as if there were `prior_weight` more reviews
+    /// scoring `prior_score`. With little to go on, this stays close to the
+    /// prior rather than trusting a handful of reviews.
+    pub fn weighted_score(&self, prior_score: f64, prior_weight: f64) -> f64 {
+        let (total, review_count) = self.totals();
+        if review_count + prior_weight == 0.0 {
+            return prior_score;
+        }
+        (prior_score * prior_weight + total) / (prior_weight + review_count)
+    }
+
+    /// Roll several ratings up into one, as if all their reviews were of the
+    /// same thing.
+    pub fn combine<'a>(ratings: impl IntoIterator<Item = &'a Rating>) -> Self {
+        let mut histogram = [0; 5];
+        for rating in ratings {
+            for (total, count) in histogram.iter_mut().zip(rating.histogram) {
+                *total += count;
+            }
+        }
+        Self::from_histogram(histogram)
+    }
+
+    fn update_summary(&mut self) {
+        self.review_count = self.histogram.iter().sum();
+        let (total, review_count) = self.totals();
+        self.average_score = (self.review_count > 0).then(|| total / review_count);
+    }
+
+    /// The score total and review count, for averaging.
+    fn totals(&self) -> (f64, f64) {
+        // Review counts are nowhere near large enough to lose precision
+        #[allow(clippy::cast_precision_loss)]
+        (self.total() as f64, self.review_count as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_histogram() {
+        let rating = Rating::from_histogram([1, 0, 0, 2, 1]);
+        assert_eq!(rating.review_count, 4);
+        assert_eq!(rating.average_score, Some(3.5));
+        assert_eq!(rating.histogram, [1, 0, 0, 2, 1]);
+
+        let rating = Rating::from_histogram([0; 5]);
+        assert_eq!(rating, Rating::default());
+        assert_eq!(rating.average_score, None);
+    }
+
+    #[test]
+    fn test_add() {
+        let mut rating = Rating::default();
+        rating.add(5, 2);
+        rating.add(2, 1);
+        assert_eq!(rating, Rating::from_histogram([0, 1, 0, 0, 2]));
+        assert_eq!(rating.total(), 12);
+        assert_eq!(rating.average_score, Some(4.0));
+
+        rating.add(0, 1);
+        rating.add(6, 1);
+        assert_eq!(rating.review_count, 3);
+    }
+
+    #[test]
+    fn test_weighted_score() {
+        let one_review = Rating::from_histogram([0, 0, 0, 0, 1]);
+        assert!((one_review.weighted_score(3.0, 0.0) - 5.0).abs() < 1e-9);
+        assert!((one_review.weighted_score(3.0, 1.0) - 4.0).abs() < 1e-9);
+
+        // Without reviews, only the prior is left
+        assert!((Rating::default().weighted_score(3.5, 10.0) - 3.5).abs() < 1e-9);
+        assert!((Rating::default().weighted_score(3.5, 0.0) - 3.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_combine() {
+        let ratings = [
+            Rating::from_histogram([0, 0, 0, 0, 1]),
+            Rating::default(),
+            Rating::from_histogram([0, 1, 0, 0, 0]),
+        ];
+
+        let rating = Rating::combine(&ratings);
+        assert_eq!(rating.review_count, 2);
+        assert_eq!(rating.average_score, Some(3.5));
+        assert_eq!(rating.histogram, [0, 1, 0, 0, 1]);
+
+        assert_eq!(Rating::combine([]), Rating::default());
+    }
+}
@@ -0,0 +1,305 @@
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::MySqlPool;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// A refresh token as persisted in the `refresh_token` table.
+///
+/// The signed JWT only carries `token_id` (as `jti`); everything else needed
+/// to decide whether the token may still be used lives here.
+#[derive(Debug)]
+pub struct RefreshToken {
+    pub token_id: String,
+    pub family_id: String,
+    pub nomer_id: i64,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl RefreshToken {
+    /// Record a new refresh token for `nomer_id` and return its token id.
+    ///
+    /// A new family is started unless `family_id` is given, which is the case
+    /// when rotating an existing token.
+    pub async fn issue(
+        db: &MySqlPool,
+        nomer_id: i64,
+        family_id: Option<&str>,
+        lifetime: i64,
+    ) -> Result<String, (StatusCode, &'static str)> {
+        let token_id = Uuid::new_v4().to_string();
+        let family_id = family_id.map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+        let issued_at = Utc::now();
+        let expires_at = issued_at + Duration::seconds(lifetime);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO refresh_token (token_id, family_id, nomer_id, issued_at, expires_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            token_id,
+            family_id,
+            nomer_id,
+            issued_at.naive_utc(),
+            expires_at.naive_utc()
+        )
+        .execute(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to store refresh token: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(token_id)
+    }
+
+    /// Mark the token `token_id` as used and return it, so that a successor
+    /// can be issued in the same family.
+    ///
+    /// Presenting a token that was already rotated is treated as theft: the
+    /// whole family is revoked, logging out both the thief and the victim.
+    /// The token must also have been issued to `nomer_id`, the subject of the
+    /// presented claim.
+    pub async fn rotate(
+        db: &MySqlPool,
+        token_id: &str,
+        nomer_id: i64,
+    ) -> Result<Self, (StatusCode, &'static str)> {
+        let Some(token) = Self::fetch(db, token_id).await? else {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid refresh token"));
+        };
+
+        if token.nomer_id != nomer_id {
+            warn!(
+                "Refresh token {} presented for nomer {} but issued to nomer {}",
+                token.token_id, nomer_id, token.nomer_id
+            );
+            return Err((StatusCode::UNAUTHORIZED, "Invalid refresh token"));
+        }
+
+        if token.revoked_at.is_some() {
+            return Err((StatusCode::UNAUTHORIZED, "Refresh token revoked"));
+        }
+
+        if token.used_at.is_some() {
+            warn!(
+                "Refresh token {} reused, revoking family {}",
+                token.token_id, token.family_id
+            );
+            Self::revoke_family(db, &token.family_id).await?;
+            return Err((StatusCode::UNAUTHORIZED, "Refresh token reused"));
+        }
+
+        if token.expires_at <= Utc::now() {
+            return Err((StatusCode::UNAUTHORIZED, "Refresh token expired"));
+        }
+
+        // Only one concurrent request may consume the token; the loser is
+        // indistinguishable from a replay and is handled as such.
+        let result = sqlx::query!(
+            r#"
+            UPDATE refresh_token
+            SET used_at = ?
+            WHERE token_id = ? AND used_at IS NULL AND revoked_at IS NULL
+            "#,
+            Utc::now().naive_utc(),
+            token_id
+        )
+        .execute(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to rotate refresh token: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        if result.rows_affected() == 0 {
+            warn!(
+                "Refresh token {} reused concurrently, revoking family {}",
+                token.token_id, token.family_id
+            );
+            Self::revoke_family(db, &token.family_id).await?;
+            return Err((StatusCode::UNAUTHORIZED, "Refresh token reused"));
+        }
+
+        Ok(token)
+    }
+
+    /// Revoke the token `token_id` along with the rest of its family.
+    ///
+    /// Revoking an unknown or already revoked token is not an error.
+    pub async fn revoke(db: &MySqlPool, token_id: &str) -> Result<(), (StatusCode, &'static str)> {
+        match Self::fetch(db, token_id).await? {
+            Some(token) => Self::revoke_family(db, &token.family_id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Revoke every token in the family `family_id`.
+    pub async fn revoke_family(
+        db: &MySqlPool,
+        family_id: &str,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        sqlx::query!(
+            r#"
+            UPDATE refresh_token
+            SET revoked_at = ?
+            WHERE family_id = ? AND revoked_at IS NULL
+            "#,
+            Utc::now().naive_utc(),
+            family_id
+        )
+        .execute(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to revoke refresh token family: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(())
+    }
+
+    async fn fetch(
+        db: &MySqlPool,
+        token_id: &str,
+    ) -> Result<Option<Self>, (StatusCode, &'static str)> {
+        sqlx::query_as!(
+            RefreshToken,
+            r#"
+            SELECT
+                token_id,
+                family_id,
+                nomer_id,
+                expires_at,
+                used_at,
+                revoked_at
+            FROM refresh_token
+            WHERE token_id = ?
+            "#,
+            token_id
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch refresh token: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_issue_and_rotate(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+        let token = RefreshToken::rotate(&db, &token_id, 1).await.unwrap();
+        assert_eq!(token.token_id, token_id);
+        assert_eq!(token.nomer_id, 1);
+
+        // Successor stays in the same family and is usable
+        let next_id = RefreshToken::issue(&db, 1, Some(&token.family_id), 60)
+            .await
+            .unwrap();
+        let next = RefreshToken::rotate(&db, &next_id, 1).await.unwrap();
+        assert_eq!(next.family_id, token.family_id);
+    }
+
+    #[sqlx::test]
+    async fn test_rotate_unknown_token(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let result = RefreshToken::rotate(&db, "not-a-token", 1).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid refresh token")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_rotate_other_nomer_token(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+        let result = RefreshToken::rotate(&db, &token_id, 2).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid refresh token")
+        );
+
+        // The token is left for its rightful nomer
+        assert!(RefreshToken::rotate(&db, &token_id, 1).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_rotate_expired_token(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, -60).await.unwrap();
+        let result = RefreshToken::rotate(&db, &token_id, 1).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token expired")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_reuse_revokes_family(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+        let token = RefreshToken::rotate(&db, &token_id, 1).await.unwrap();
+        let next_id = RefreshToken::issue(&db, 1, Some(&token.family_id), 60)
+            .await
+            .unwrap();
+
+        // Replaying the rotated token is rejected...
+        let result = RefreshToken::rotate(&db, &token_id, 1).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token reused")
+        );
+
+        // ...and takes the legitimate successor down with it
+        let result = RefreshToken::rotate(&db, &next_id, 1).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token revoked")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_revoke(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+        RefreshToken::revoke(&db, &token_id).await.unwrap();
+
+        let result = RefreshToken::rotate(&db, &token_id, 1).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token revoked")
+        );
+
+        // Revoking twice, or revoking nothing, is fine
+        assert!(RefreshToken::revoke(&db, &token_id).await.is_ok());
+        assert!(RefreshToken::revoke(&db, "not-a-token").await.is_ok());
+    }
+}
@@ -5,9 +5,14 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "camelCase")]
 pub struct Review {
     pub id: i64,
-    pub nomer_id: i64,
+    /// `None` once the author deleted their account but kept their reviews.
+    pub nomer_id: Option<i64>,
     pub store_id: i64,
+    /// The item of the store reviewed, or `None` for the store as a whole.
+    pub item_id: Option<i64>,
     pub score: i64,
     pub comment: String,
     pub created_at: DateTime<Utc>,
+    /// When the review was last edited, if ever.
+    pub updated_at: Option<DateTime<Utc>>,
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::models::Item;
+use crate::models::{Item, Rating};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,5 +11,7 @@ pub struct Store {
     pub cuisine: String,
     pub information: String,
     pub image_url: String,
+    /// Of reviews of the store as a whole, leaving out those of its items.
+    pub rating: Rating,
     pub items: Vec<Item>,
 }
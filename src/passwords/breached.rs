@@ -0,0 +1,299 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use sha1::{Digest, Sha1};
+use tracing::error;
+
+use crate::error_ctx;
+
+/// Length of the SHA-1 prefix range files are named after.
+const PREFIX_LEN: usize = 5;
+
+/// SHA-1 hashes of passwords known from data breaches, as published by
+/// Have I Been Pwned.
+///
+/// Either format of its Pwned Passwords downloader can be used: a single
+/// file of `<SHA-1>:<count>` lines, or a directory of k-anonymity range
+/// files, each named after a 5 character hash prefix and holding
+/// `<suffix>:<count>` lines for the hashes sharing that prefix. The lists
+/// run to tens of gigabytes, so they are searched on disk rather than
+/// loaded.
+pub(super) enum BreachedPasswords {
+    /// A directory of range files, of which only the one for the prefix of
+    /// a hash is read to look it up.
+    Ranges(PathBuf),
+    /// A single file, which must be sorted by hash (as the downloader writes
+    /// it) to be binary searched.
+    Sorted(PathBuf),
+    /// Sorted hashes, so that tests need not write a list to disk.
+    #[cfg(test)]
+    Memory(Vec<[u8; 20]>),
+}
+
+impl BreachedPasswords {
+    /// Check that `path` holds a hash list, without reading all of it.
+    pub fn load(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            let mut entries =
+                fs::read_dir(path).with_context(error_ctx!("Failed to read {}", path.display()))?;
+            let has_ranges = entries.any(|entry| {
+                entry
+                    .ok()
+                    .and_then(|entry| range_prefix(&entry.path()))
+                    .is_some()
+            });
+            if !has_ranges {
+                bail!(error_ctx!(
+                    "No hash prefix range files in {}",
+                    path.display()
+                )());
+            }
+            return Ok(Self::Ranges(path.to_path_buf()));
+        }
+
+        let file =
+            File::open(path).with_context(error_ctx!("Failed to read {}", path.display()))?;
+        let mut line = String::new();
+        BufReader::new(file)
+            .read_line(&mut line)
+            .with_context(error_ctx!("Failed to read {}", path.display()))?;
+        if parse_line("", line.trim()).is_none() {
+            bail!(error_ctx!("Invalid hash on line 1 of {}", path.display())());
+        }
+        Ok(Self::Sorted(path.to_path_buf()))
+    }
+
+    /// Whether `password` is in the list.
+    ///
+    /// A list that cannot be read is logged and treated as not containing
+    /// it, so that nomers can still change their passwords meanwhile.
+    pub fn contains(&self, password: &str) -> bool {
+        let hash: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+        let found = match self {
+            Self::Ranges(dir) => search_range(dir, &hash),
+            Self::Sorted(path) => search_sorted(path, &hash),
+            #[cfg(test)]
+            Self::Memory(hashes) => Ok(hashes.binary_search(&hash).is_ok()),
+        };
+
+        found.unwrap_or_else(|e| {
+            error!("Failed to look up breached password: {e:?}");
+            false
+        })
+    }
+}
+
+#[cfg(test)]
+impl BreachedPasswords {
+    pub fn from_passwords(passwords: &[&str]) -> Self {
+        let mut hashes: Vec<[u8; 20]> = passwords
+            .iter()
+            .map(|password| Sha1::digest(password.as_bytes()).into())
+            .collect();
+        hashes.sort_unstable();
+        Self::Memory(hashes)
+    }
+}
+
+/// The hash prefix a range file at `path` holds, if it is one.
+fn range_prefix(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    (stem.len() == PREFIX_LEN && stem.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| stem.to_string())
+}
+
+/// Look `hash` up in the range file for its prefix in `dir`.
+fn search_range(dir: &Path, hash: &[u8; 20]) -> Result<bool> {
+    // The first PREFIX_LEN hex digits
+    let prefix = format!("{:02X}{:02X}{:X}", hash[0], hash[1], hash[2] >> 4);
+    let path = dir.join(format!("{prefix}.txt"));
+
+    // Missing ranges are simply not in the list
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(error_ctx!("Failed to read {}", path.display())),
+    };
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(error_ctx!("Failed to read {}", path.display()))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(found) = parse_line(&prefix, line) else {
+            bail!(error_ctx!(
+                "Invalid hash on line {} of {}",
+                number + 1,
+                path.display()
+            )());
+        };
+        if found == *hash {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Binary search the sorted hash list at `path` for `hash`.
+///
+/// Lines differ in length, so the search narrows down a range of byte
+/// offsets, looking at the first line starting in the middle of it.
+fn search_sorted(path: &Path, hash: &[u8; 20]) -> Result<bool> {
+    let read_error = error_ctx!("Failed to read {}", path.display());
+    let mut file = BufReader::new(File::open(path).with_context(read_error)?);
+    let mut low = 0;
+    let mut high = file.get_ref().metadata().with_context(read_error)?.len();
+    let mut line = String::new();
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        // Skip the rest of the line `middle` falls in, unless it starts there
+        let start = if middle == 0 {
+            file.seek(SeekFrom::Start(0)).with_context(read_error)?;
+            0
+        } else {
+            file.seek(SeekFrom::Start(middle - 1))
+                .with_context(read_error)?;
+            line.clear();
+            let skipped = file.read_line(&mut line).with_context(read_error)?;
+            middle - 1 + skipped as u64
+        };
+        if start >= high {
+            high = middle;
+            continue;
+        }
+
+        line.clear();
+        let len = file.read_line(&mut line).with_context(read_error)?;
+        let line = line.trim();
+        // Blank lines can only trail the hashes, so count them as past all
+        let found = if line.is_empty() {
+            None
+        } else {
+            let Some(found) = parse_line("", line) else {
+                bail!(error_ctx!(
+                    "Invalid hash at byte {start} of {}",
+                    path.display()
+                )());
+            };
+            Some(found)
+        };
+
+        match found {
+            Some(found) if found == *hash => return Ok(true),
+            Some(found) if found < *hash => low = start + len as u64,
+            _ => high = middle,
+        }
+    }
+
+    Ok(false)
+}
+
+/// Parse a `<suffix>:<count>` line of a list of hashes starting with
+/// `prefix`.
+fn parse_line(prefix: &str, line: &str) -> Option<[u8; 20]> {
+    // Breach counts are of no use to us
+    let suffix = line.split_once(':').map_or(line, |(suffix, _)| suffix);
+    parse_hash(&format!("{prefix}{suffix}"))
+}
+
+fn parse_hash(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0u8; 20];
+    for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// SHA-1 of `password`, in upper case hex.
+    const PASSWORD_SHA1: &str = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nomnom-breached-{}-{name}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        let hash = parse_hash(PASSWORD_SHA1).unwrap();
+        assert_eq!(hash, <[u8; 20]>::from(Sha1::digest(b"password")));
+        assert_eq!(parse_hash(&PASSWORD_SHA1.to_lowercase()), Some(hash));
+
+        assert_eq!(parse_hash(&PASSWORD_SHA1[1..]), None);
+        assert_eq!(parse_hash(&PASSWORD_SHA1.replace('5', "G")), None);
+    }
+
+    #[test]
+    fn test_load_file() {
+        let path = temp_path("hashes.txt");
+        fs::write(&path, format!("{PASSWORD_SHA1}:9545824\n\n")).unwrap();
+
+        let breached = BreachedPasswords::load(&path).unwrap();
+        assert!(breached.contains("password"));
+        assert!(!breached.contains("Password"));
+
+        fs::write(&path, "not a hash:1\n").unwrap();
+        assert!(BreachedPasswords::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_search_sorted() {
+        let passwords: Vec<String> = (0..200).map(|i| format!("password{i}")).collect();
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .map(|password| format!("{:X}:1\n", Sha1::digest(password.as_bytes())))
+            .collect();
+        lines.sort_unstable();
+        let path = temp_path("sorted.txt");
+        fs::write(&path, lines.concat()).unwrap();
+
+        let breached = BreachedPasswords::load(&path).unwrap();
+        for password in &passwords {
+            assert!(breached.contains(password), "{password} not found");
+        }
+        assert!(!breached.contains("password200"));
+        assert!(!breached.contains(""));
+        fs::remove_file(&path).unwrap();
+
+        // An unreadable list lets every password through
+        assert!(!BreachedPasswords::Sorted(temp_path("missing.txt")).contains("password0"));
+    }
+
+    #[test]
+    fn test_load_range_files() {
+        let dir = temp_path("ranges");
+        fs::create_dir(&dir).unwrap();
+        assert!(BreachedPasswords::load(&dir).is_err());
+
+        let (prefix, suffix) = PASSWORD_SHA1.split_at(PREFIX_LEN);
+        fs::write(
+            dir.join(format!("{prefix}.txt")),
+            format!("0000000000000000000000000000000000A:1\r\n{suffix}:9545824\r\n"),
+        )
+        .unwrap();
+        fs::write(dir.join("README.md"), "Not a range file").unwrap();
+
+        let breached = BreachedPasswords::load(&dir).unwrap();
+        assert!(breached.contains("password"));
+        // Only the range file for its prefix is read, and there is none
+        assert!(!breached.contains("Password"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
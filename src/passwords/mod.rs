@@ -0,0 +1,344 @@
+mod breached;
+mod strength;
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use argon2::{
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use axum::http::StatusCode;
+use tokio::task::spawn_blocking;
+use tracing::{error, info};
+
+use breached::BreachedPasswords;
+
+use crate::{config::Config, error_ctx};
+
+/// Shortest and longest passwords allowed, in bytes.
+const MIN_LENGTH: usize = 8;
+const MAX_LENGTH: usize = 100;
+
+/// Shortest email or display name that passwords may not contain, so that
+/// a nomer called `Al` can still use `calendar` in theirs.
+const MIN_PERSONAL_LENGTH: usize = 3;
+
+/// Hashes and verifies nomer passwords with Argon2id, and decides which new
+/// passwords are good enough to use.
+///
+/// Hashing is deliberately slow, so it runs on the blocking thread pool
+/// rather than stalling the async runtime.
+#[derive(Clone)]
+pub(crate) struct Passwords {
+    /// Cost parameters new hashes are made with.
+    params: Params,
+    /// Hash of a random password, verified against when the email is unknown.
+    dummy_hash: Arc<str>,
+    /// Lowest strength score, from 0 to 4, new passwords must reach.
+    min_strength: u8,
+    /// Passwords known from data breaches, if a list was given.
+    breached: Option<Arc<BreachedPasswords>>,
+}
+
+/// The rule a new password broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PasswordError {
+    Length,
+    ContainsEmail,
+    ContainsDisplayName,
+    Breached,
+    TooWeak,
+}
+
+impl PasswordError {
+    /// Machine-readable name of the rule, for clients to act on.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Length => "length",
+            Self::ContainsEmail => "contains_email",
+            Self::ContainsDisplayName => "contains_display_name",
+            Self::Breached => "breached",
+            Self::TooWeak => "too_weak",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::Length => "Password must be 8 to 100 characters long",
+            Self::ContainsEmail => "Password must not contain your email",
+            Self::ContainsDisplayName => "Password must not contain your display name",
+            Self::Breached => "Password has appeared in a data breach",
+            Self::TooWeak => "Password is too easy to guess",
+        }
+    }
+}
+
+impl From<PasswordError> for (StatusCode, &'static str) {
+    fn from(err: PasswordError) -> Self {
+        (StatusCode::BAD_REQUEST, err.message())
+    }
+}
+
+impl Passwords {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let params = Params::new(
+            config.argon2_memory_cost,
+            config.argon2_time_cost,
+            config.argon2_parallelism,
+            None,
+        )
+        .map_err(|e| anyhow!(error_ctx!("Invalid Argon2 parameters: {e}")()))?;
+
+        let breached = match &config.breached_passwords {
+            Some(path) => {
+                let breached = BreachedPasswords::load(path)
+                    .with_context(error_ctx!("Failed to load breached passwords"))?;
+                info!("Checking new passwords against {}", path.display());
+                Some(Arc::new(breached))
+            }
+            None => None,
+        };
+
+        Ok(Self::new(params, config.password_min_strength, breached))
+    }
+
+    fn new(params: Params, min_strength: u8, breached: Option<Arc<BreachedPasswords>>) -> Self {
+        let dummy_hash = hash_with(&params, "dummy password")
+            .expect("hashing a fixed password cannot fail")
+            .into();
+
+        Self {
+            params,
+            dummy_hash,
+            min_strength,
+            breached,
+        }
+    }
+
+    /// Check that `password` is fit to be the new password of the nomer
+    /// with `email` and `display_name`, returning the first rule it breaks.
+    pub async fn validate(
+        &self,
+        password: &str,
+        email: &str,
+        display_name: &str,
+    ) -> Result<(), PasswordError> {
+        if !(MIN_LENGTH..=MAX_LENGTH).contains(&password.len()) {
+            return Err(PasswordError::Length);
+        }
+
+        let lowercase = password.to_lowercase();
+        let contains = |personal: &str| {
+            let personal = personal.trim().to_lowercase();
+            personal.len() >= MIN_PERSONAL_LENGTH && lowercase.contains(&personal)
+        };
+        let local_part = email.rsplit_once('@').map_or(email, |(local, _)| local);
+        if contains(email) || contains(local_part) {
+            return Err(PasswordError::ContainsEmail);
+        }
+        if contains(display_name) {
+            return Err(PasswordError::ContainsDisplayName);
+        }
+
+        if self.is_breached(password).await {
+            return Err(PasswordError::Breached);
+        }
+
+        if strength::score(password) < self.min_strength {
+            return Err(PasswordError::TooWeak);
+        }
+
+        Ok(())
+    }
+
+    /// Whether `password` is in the breached password list, if one was
+    /// given. The list is searched on disk, so on the blocking thread pool.
+    async fn is_breached(&self, password: &str) -> bool {
+        let Some(breached) = self.breached.clone() else {
+            return false;
+        };
+        let password = password.to_string();
+
+        spawn_blocking(move || breached.contains(&password))
+            .await
+            .unwrap_or_else(|e| {
+                error!("Breached password lookup task failed: {e}");
+                false
+            })
+    }
+
+    /// Hash `password` with the configured parameters.
+    pub async fn hash(&self, password: &str) -> Option<String> {
+        let params = self.params.clone();
+        let password = password.to_string();
+
+        spawn_blocking(move || hash_with(&params, &password))
+            .await
+            .map_err(|e| error!("Password hashing task failed: {e}"))
+            .ok()
+            .flatten()
+    }
+
+    /// Check `password` against `hash`, using the parameters `hash` was made
+    /// with. Returns `None` if `hash` is malformed.
+    pub async fn verify(&self, password: &str, hash: &str) -> Option<bool> {
+        let password = password.to_string();
+        let hash = hash.to_string();
+
+        spawn_blocking(move || {
+            let parsed_hash = PasswordHash::new(&hash).ok()?;
+            Some(
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed_hash)
+                    .is_ok(),
+            )
+        })
+        .await
+        .map_err(|e| error!("Password verification task failed: {e}"))
+        .ok()
+        .flatten()
+    }
+
+    /// Whether `hash` was made with another algorithm or parameters than
+    /// new hashes are, and so should be replaced.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(hash) else {
+            return false;
+        };
+        let Ok(params) = Params::try_from(&parsed_hash) else {
+            return false;
+        };
+
+        parsed_hash.algorithm != Algorithm::Argon2id.ident()
+            || parsed_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+
+    /// Hash that costs as much to verify as a real one, for when there is
+    /// no real one to verify against.
+    pub fn dummy_hash(&self) -> &str {
+        &self.dummy_hash
+    }
+}
+
+fn hash_with(params: &Params, password: &str) -> Option<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .ok()
+}
+
+#[cfg(test)]
+impl Passwords {
+    /// Cheapest parameters Argon2 allows, to keep tests fast.
+    pub fn for_testing() -> Self {
+        Self::new(
+            Params::new(Params::MIN_M_COST, Params::MIN_T_COST, 1, None).unwrap(),
+            3,
+            Some(Arc::new(BreachedPasswords::from_passwords(&[
+                "breached_password",
+            ]))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_and_verify() {
+        let passwords = Passwords::for_testing();
+        let hash = passwords.hash("test_password").await.unwrap();
+        let hash_2 = passwords.hash("test_password").await.unwrap();
+        assert_ne!(hash, hash_2);
+
+        assert_eq!(passwords.verify("test_password", &hash).await, Some(true));
+        assert_eq!(passwords.verify("wrong_password", &hash).await, Some(false));
+        assert_eq!(
+            passwords
+                .verify("test_password", "invalid_hash_format")
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_needs_rehash() {
+        let passwords = Passwords::for_testing();
+        let hash = passwords.hash("test_password").await.unwrap();
+        assert!(!passwords.needs_rehash(&hash));
+
+        // Hashes made with other parameters still verify, but are outdated
+        let old_hash = Passwords::new(Params::new(16, 2, 1, None).unwrap(), 3, None)
+            .hash("test_password")
+            .await
+            .unwrap();
+        assert_eq!(
+            passwords.verify("test_password", &old_hash).await,
+            Some(true)
+        );
+        assert!(passwords.needs_rehash(&old_hash));
+
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2i_hash =
+            Argon2::new(Algorithm::Argon2i, Version::V0x13, passwords.params.clone())
+                .hash_password(b"test_password", &salt)
+                .unwrap()
+                .to_string();
+        assert!(passwords.needs_rehash(&argon2i_hash));
+    }
+
+    #[test]
+    fn test_dummy_hash() {
+        let passwords = Passwords::for_testing();
+        let dummy = PasswordHash::new(passwords.dummy_hash()).unwrap();
+
+        // Just as costly as a real hash
+        assert_eq!(dummy.algorithm, Algorithm::Argon2id.ident());
+        assert!(!passwords.needs_rehash(passwords.dummy_hash()));
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let passwords = Passwords::for_testing();
+        let validate = async |password: &str| {
+            passwords
+                .validate(password, "e0123456@u.nus.edu", "Nommy")
+                .await
+        };
+
+        assert_eq!(validate("Xk2#v9Lq!m").await, Ok(()));
+        assert_eq!(validate("hungry for chicken rice at 2am").await, Ok(()));
+
+        assert_eq!(validate("short").await, Err(PasswordError::Length));
+        let long = "x".repeat(101);
+        assert_eq!(validate(&long).await, Err(PasswordError::Length));
+        assert_eq!(
+            validate("E0123456-has-a-secret").await,
+            Err(PasswordError::ContainsEmail)
+        );
+        assert_eq!(
+            validate("the great nommy 42!").await,
+            Err(PasswordError::ContainsDisplayName)
+        );
+        assert_eq!(
+            validate("breached_password").await,
+            Err(PasswordError::Breached)
+        );
+        assert_eq!(validate("password123").await, Err(PasswordError::TooWeak));
+        assert_eq!(validate("qwertyuiop").await, Err(PasswordError::TooWeak));
+
+        // Names too short to matter are allowed
+        assert_eq!(
+            passwords
+                .validate("calendar is full", "al@u.nus.edu", "Al")
+                .await,
+            Ok(())
+        );
+    }
+}
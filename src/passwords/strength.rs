@@ -0,0 +1,384 @@
+//! Password strength estimation in the manner of zxcvbn.
+//!
+//! Rather than counting character classes, a password is taken apart into
+//! the patterns an attacker would try first (common passwords, keyboard
+//! runs, sequences, repeats and years), and scored by how many guesses it
+//! would take to stumble upon that combination of patterns.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use chrono::{Datelike, Utc};
+
+/// Common passwords and words, most common first.
+const COMMON_WORDS: &str = include_str!("common_words.txt");
+
+/// Rows of a QWERTY keyboard, unshifted and shifted.
+const KEYBOARD_ROWS: [&str; 8] = [
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+    "~!@#$%^&*()_+",
+    "qwertyuiop{}|",
+    "asdfghjkl:\"",
+    "zxcvbnm<>?",
+];
+
+/// Keys on a QWERTY keyboard, and how many neighbours each has on average.
+const KEYBOARD_STARTS: f64 = 94.0;
+const KEYBOARD_DEGREE: f64 = 4.6;
+
+/// Characters commonly swapped in for letters, and the letters they stand
+/// for.
+const L33T: [(char, char); 10] = [
+    ('4', 'a'),
+    ('@', 'a'),
+    ('3', 'e'),
+    ('1', 'i'),
+    ('!', 'i'),
+    ('0', 'o'),
+    ('5', 's'),
+    ('$', 's'),
+    ('7', 't'),
+    ('+', 't'),
+];
+
+/// Fewest guesses credited to any pattern that is only part of a password.
+const MIN_SUBMATCH_GUESSES: f64 = 50.0;
+
+/// Guesses each further pattern in a password adds at the very least.
+const MIN_GUESSES_PER_PATTERN: f64 = 10_000.0;
+
+static DICTIONARY: LazyLock<HashMap<&'static str, usize>> = LazyLock::new(|| {
+    COMMON_WORDS
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .enumerate()
+        .map(|(rank, word)| (word, rank + 1))
+        .collect()
+});
+
+/// A run of characters `start..end` that fits a pattern, and the log10 of
+/// the guesses needed to find it.
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    log_guesses: f64,
+}
+
+/// Score `password` from 0 (too guessable) to 4 (very unguessable), on the
+/// same scale as zxcvbn.
+pub(super) fn score(password: &str) -> u8 {
+    match log_guesses(&password.chars().collect::<Vec<_>>()) {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+/// Log10 of the guesses needed to find `password` by trying its patterns.
+fn log_guesses(password: &[char]) -> f64 {
+    let n = password.len();
+    if n == 0 {
+        return 0.0;
+    }
+
+    let mut matches = Vec::new();
+    dictionary_matches(password, &mut matches);
+    sequence_matches(password, &mut matches);
+    keyboard_matches(password, &mut matches);
+    repeat_matches(password, &mut matches);
+    year_matches(password, &mut matches);
+    // Anything else has to be brute forced
+    for start in 0..n {
+        for end in start + 1..=n {
+            matches.push(Match {
+                start,
+                end,
+                log_guesses: bruteforce_log_guesses(end - start),
+            });
+        }
+    }
+    // Patterns are only guessed as a whole if they are the whole password
+    for m in &mut matches {
+        if m.end - m.start < n {
+            m.log_guesses = m.log_guesses.max(MIN_SUBMATCH_GUESSES.log10());
+        }
+    }
+
+    // best[k][end] is the fewest guesses for password[..end] made up of k
+    // patterns, which every arrangement of those patterns multiplies
+    let mut best = vec![vec![f64::INFINITY; n + 1]; n + 1];
+    best[0][0] = 0.0;
+    for k in 0..n {
+        for m in &matches {
+            let guesses = best[k][m.start] + m.log_guesses;
+            if guesses < best[k + 1][m.end] {
+                best[k + 1][m.end] = guesses;
+            }
+        }
+    }
+
+    (1..=n)
+        .filter(|&k| best[k][n].is_finite())
+        .map(|k| {
+            let arrangements = (1..=k).map(|i| float(i).log10()).sum::<f64>();
+            let additive = float(k - 1) * MIN_GUESSES_PER_PATTERN.log10();
+            log10_sum(arrangements + best[k][n], additive)
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Common passwords and words, as typed, reversed or in l33t speak.
+fn dictionary_matches(password: &[char], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = password.iter().map(|&c| to_lower(c)).collect();
+    let unl33t: Vec<char> = lower
+        .iter()
+        .map(|&c| {
+            L33T.iter()
+                .find(|(sub, _)| *sub == c)
+                .map_or(c, |&(_, c)| c)
+        })
+        .collect();
+
+    for start in 0..password.len() {
+        for end in start + 3..=password.len() {
+            let original = &password[start..end];
+            let caps = uppercase_variations(original).log10();
+            let word: String = lower[start..end].iter().collect();
+            let mut candidates = vec![(word.clone(), 0.0)];
+            candidates.push((word.chars().rev().collect(), 2f64.log10()));
+            if unl33t[start..end] != lower[start..end] {
+                let subs = (start..end).filter(|&i| unl33t[i] != lower[i]).count();
+                candidates.push((
+                    unl33t[start..end].iter().collect(),
+                    float(subs) * 2f64.log10(),
+                ));
+            }
+
+            for (candidate, extra) in candidates {
+                if let Some(&rank) = DICTIONARY.get(candidate.as_str()) {
+                    matches.push(Match {
+                        start,
+                        end,
+                        log_guesses: float(rank).log10() + caps + extra,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Runs like `abcd`, `7531` or `zyx`, stepping evenly through the
+/// alphabet or digits.
+fn sequence_matches(password: &[char], matches: &mut Vec<Match>) {
+    let delta =
+        |i: usize| i64::from(u32::from(password[i])) - i64::from(u32::from(password[i - 1]));
+
+    let mut start = 0;
+    while start + 2 < password.len() {
+        let step = delta(start + 1);
+        let mut end = start + 2;
+        while end < password.len() && delta(end) == step {
+            end += 1;
+        }
+
+        let is_sequence = step != 0 && step.abs() <= 5;
+        if is_sequence && end - start >= 3 {
+            let first = password[start];
+            let base: f64 = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else if first.is_lowercase() {
+                26.0
+            } else {
+                52.0
+            };
+            let direction: f64 = if step < 0 { 2.0 } else { 1.0 };
+            matches.push(Match {
+                start,
+                end,
+                log_guesses: (base * direction * float(end - start)).log10(),
+            });
+        }
+        start = end - 1;
+    }
+}
+
+/// Runs of neighbouring keys along a keyboard row, like `qwerty` or `;lkj`.
+fn keyboard_matches(password: &[char], matches: &mut Vec<Match>) {
+    for row in KEYBOARD_ROWS {
+        let row: Vec<char> = row.chars().collect();
+        let position = |c: char| row.iter().position(|&k| k == to_lower(c));
+
+        let mut start = 0;
+        while start < password.len() {
+            let mut end = start + 1;
+            let mut forwards = None;
+            while end < password.len() {
+                let (Some(prev), Some(next)) =
+                    (position(password[end - 1]), position(password[end]))
+                else {
+                    break;
+                };
+                if next.abs_diff(prev) != 1 || forwards.is_some_and(|f| f != (next > prev)) {
+                    break;
+                }
+                forwards = Some(next > prev);
+                end += 1;
+            }
+
+            if end - start >= 3 {
+                let length = float(end - start);
+                let shifted = if password[start..end].iter().any(|c| c.is_uppercase()) {
+                    2.0
+                } else {
+                    1.0
+                };
+                matches.push(Match {
+                    start,
+                    end,
+                    log_guesses: ((length - 1.0) * KEYBOARD_STARTS * KEYBOARD_DEGREE * shifted)
+                        .log10(),
+                });
+            }
+            start = end;
+        }
+    }
+}
+
+/// The same character or chunk over and over, like `aaaa` or `abcabc`.
+fn repeat_matches(password: &[char], matches: &mut Vec<Match>) {
+    let mut start = 0;
+    while start < password.len() {
+        // The chunk repeated the furthest from here, the shortest on a tie
+        let longest = (1..=(password.len() - start) / 2)
+            .map(|unit| {
+                let chunk = &password[start..start + unit];
+                let count = password[start..]
+                    .chunks_exact(unit)
+                    .take_while(|c| *c == chunk)
+                    .count();
+                (unit, count)
+            })
+            .filter(|&(unit, count)| count >= 2 && unit * count >= 3)
+            .max_by_key(|&(unit, count)| (unit * count, std::cmp::Reverse(unit)));
+
+        let Some((unit, count)) = longest else {
+            start += 1;
+            continue;
+        };
+        // Repeats are guessed by guessing the chunk, then how often it goes
+        let chunk = &password[start..start + unit];
+        matches.push(Match {
+            start,
+            end: start + unit * count,
+            log_guesses: log_guesses(chunk) + float(count).log10(),
+        });
+        start += unit * count;
+    }
+}
+
+/// Recent years, which are guessed starting from this one.
+fn year_matches(password: &[char], matches: &mut Vec<Match>) {
+    let this_year = Utc::now().year();
+
+    for start in 0..password.len().saturating_sub(3) {
+        let digits: String = password[start..start + 4].iter().collect();
+        let Ok(year) = digits.parse::<i32>() else {
+            continue;
+        };
+        if digits.chars().all(|c| c.is_ascii_digit()) && (1900..=2099).contains(&year) {
+            matches.push(Match {
+                start,
+                end: start + 4,
+                log_guesses: f64::from((year - this_year).abs().max(20)).log10(),
+            });
+        }
+    }
+}
+
+/// Each character brute forced is taken to be one of 10, as zxcvbn does.
+fn bruteforce_log_guesses(length: usize) -> f64 {
+    float(length)
+}
+
+/// Ways `word` could have been capitalized, from a dictionary word.
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+
+    let first_only = upper == 1 && word.first().is_some_and(|c| c.is_uppercase());
+    let last_only = upper == 1 && word.last().is_some_and(|c| c.is_uppercase());
+    if upper == 0 {
+        1.0
+    } else if lower == 0 || first_only || last_only {
+        2.0
+    } else {
+        (1..=upper.min(lower))
+            .map(|i| binomial(upper + lower, i))
+            .sum()
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (1..=k).fold(1.0, |acc, i| acc * float(n + 1 - i) / float(i))
+}
+
+/// A count as a float, which is exact for anything as short as a password.
+fn float(n: usize) -> f64 {
+    f64::from(u32::try_from(n).unwrap_or(u32::MAX))
+}
+
+fn to_lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Log10 of the sum of two numbers given by their log10s.
+fn log10_sum(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    high + (1.0 + 10f64.powf(low - high)).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_common() {
+        assert_eq!(score("password"), 0);
+        assert_eq!(score("Password"), 0);
+        assert_eq!(score("p@ssw0rd"), 0);
+        assert_eq!(score("drowssap"), 0);
+        assert!(score("password1") <= 1);
+    }
+
+    #[test]
+    fn test_score_patterns() {
+        assert!(score("abcdefghij") <= 1);
+        assert!(score("qwertyuiop") <= 1);
+        assert!(score("zxcvbnm,./") <= 1);
+        assert!(score("aaaaaaaaaaaa") <= 1);
+        assert!(score("abcabcabcabc") <= 1);
+        assert!(score("1234567890") <= 1);
+        assert!(score("19991999") <= 2);
+    }
+
+    #[test]
+    fn test_score_strong() {
+        assert!(score("correct horse battery staple") >= 3);
+        assert_eq!(score("xK9#mQ2$vL7!"), 4);
+        assert!(score("Tr0ub4dor&3") >= 2);
+    }
+
+    #[test]
+    fn test_log10_sum() {
+        assert!((log10_sum(2.0, 2.0) - 200f64.log10()).abs() < 1e-9);
+        assert!((log10_sum(0.0, 3.0) - 1001f64.log10()).abs() < 1e-9);
+    }
+}
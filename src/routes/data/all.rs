@@ -1,9 +1,11 @@
+use std::collections::HashMap;
+
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use bigdecimal::BigDecimal;
 use sqlx::MySqlPool;
 
 use crate::{
-    models::{Canteen, Item, Store},
+    models::{Canteen, Item, Rating, Store},
     state::AppState,
 };
 
@@ -42,10 +44,36 @@ struct DbItem {
     image_url: String,
 }
 
+/// Number of reviews giving a store, or an item of it, a score
+struct DbScoreCount {
+    store_id: i64,
+    item_id: Option<i64>,
+    score: i64,
+    review_count: i64,
+}
+
+/// Ratings of stores and of items, by their IDs
+#[derive(Default)]
+struct Ratings {
+    stores: HashMap<i64, Rating>,
+    items: HashMap<i64, Rating>,
+}
+
+impl Ratings {
+    fn store(&self, store_id: i64) -> Rating {
+        self.stores.get(&store_id).cloned().unwrap_or_default()
+    }
+
+    fn item(&self, item_id: i64) -> Rating {
+        self.items.get(&item_id).cloned().unwrap_or_default()
+    }
+}
+
 async fn get_all_data(db: &MySqlPool) -> Result<Vec<Canteen>, (StatusCode, &'static str)> {
     let locations = fetch_all_locations(db).await?;
     let stores = fetch_all_stores(db).await?;
     let items = fetch_all_items(db).await?;
+    let ratings = build_ratings(fetch_score_counts(db).await?);
 
     let locations = locations
         .into_iter()
@@ -64,6 +92,7 @@ async fn get_all_data(db: &MySqlPool) -> Result<Vec<Canteen>, (StatusCode, &'sta
                             is_available: ite.is_available,
                             information: ite.information.clone(),
                             image_url: ite.image_url.clone(),
+                            rating: ratings.item(ite.item_id),
                         })
                         .collect::<Vec<Item>>();
 
@@ -74,6 +103,7 @@ async fn get_all_data(db: &MySqlPool) -> Result<Vec<Canteen>, (StatusCode, &'sta
                         cuisine: sto.cuisine.clone(),
                         information: sto.information.clone(),
                         image_url: sto.image_url.clone(),
+                        rating: ratings.store(sto.store_id),
                         items: items_at_store,
                     }
                 })
@@ -85,6 +115,7 @@ async fn get_all_data(db: &MySqlPool) -> Result<Vec<Canteen>, (StatusCode, &'sta
                 latitude: cant.latitude.clone(),
                 longitude: cant.longitude.clone(),
                 image_url: cant.image_url.clone(),
+                rating: Rating::combine(stores_at_loc.iter().map(|sto| &sto.rating)),
                 stores: stores_at_loc,
             }
         })
@@ -153,3 +184,117 @@ async fn fetch_all_items(db: &MySqlPool) -> Result<Vec<DbItem>, (StatusCode, &'s
     .await
     .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))
 }
+
+/// Reviews of a store as a whole count towards the store, and reviews of one
+/// of its items towards the item only.
+fn build_ratings(score_counts: Vec<DbScoreCount>) -> Ratings {
+    let mut ratings = Ratings::default();
+
+    for score_count in score_counts {
+        let rating = match score_count.item_id {
+            Some(item_id) => ratings.items.entry(item_id).or_default(),
+            None => ratings.stores.entry(score_count.store_id).or_default(),
+        };
+        rating.add(score_count.score, score_count.review_count);
+    }
+
+    ratings
+}
+
+async fn fetch_score_counts(
+    db: &MySqlPool,
+) -> Result<Vec<DbScoreCount>, (StatusCode, &'static str)> {
+    sqlx::query_as!(
+        DbScoreCount,
+        r#"
+        SELECT
+            store_id,
+            item_id AS "item_id: i64",
+            score,
+            COUNT(*) AS review_count
+        FROM review
+        GROUP BY store_id, item_id, score
+        "#
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score_count(store_id: i64, item_id: Option<i64>, score: i64, count: i64) -> DbScoreCount {
+        DbScoreCount {
+            store_id,
+            item_id,
+            score,
+            review_count: count,
+        }
+    }
+
+    #[test]
+    fn test_build_ratings() {
+        let ratings = build_ratings(vec![
+            score_count(1, None, 5, 2),
+            score_count(1, None, 2, 1),
+            score_count(1, Some(1), 1, 1),
+            score_count(2, Some(3), 4, 3),
+        ]);
+
+        assert_eq!(ratings.store(1).histogram, [0, 1, 0, 0, 2]);
+        assert_eq!(ratings.store(1).average_score, Some(4.0));
+        assert_eq!(ratings.item(1).histogram, [1, 0, 0, 0, 0]);
+        assert_eq!(ratings.item(3).review_count, 3);
+
+        // Item reviews are kept apart from the store
+        assert_eq!(ratings.store(2), Rating::default());
+        assert_eq!(ratings.item(2), Rating::default());
+    }
+
+    #[sqlx::test]
+    async fn test_get_all_data_ratings(db: MySqlPool) {
+        for i in 1..=2 {
+            sqlx::query!(
+                r#"INSERT INTO nomer (display_name, email, password_hash) 
+                   VALUES (?, ?, ?)"#,
+                format!("Test User {}", i),
+                format!("test{}@test.com", i),
+                format!("test_hash_{}", i)
+            )
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        // Items 1 and 2 are sold by store 1, and item 3 by store 2, both of
+        // which are in canteen 1
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, item_id, nomer_id, score, comment)
+               VALUES (1, NULL, 1, 5, 'Great'), (1, NULL, 2, 3, 'Fine'),
+                      (1, 1, 1, 1, 'Awful'), (2, NULL, 1, 4, 'Good')"#
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let canteens = get_all_data(&db).await.unwrap();
+        let canteen = &canteens[0];
+        assert_eq!(canteen.rating.histogram, [0, 0, 1, 1, 1]);
+        assert_eq!(canteen.rating.average_score, Some(4.0));
+
+        let store = &canteen.stores[0];
+        assert_eq!(store.rating.review_count, 2);
+        assert_eq!(store.rating.average_score, Some(4.0));
+        assert_eq!(store.items[0].rating.histogram, [1, 0, 0, 0, 0]);
+        assert_eq!(store.items[1].rating, Rating::default());
+
+        // Canteens without reviews are still rated, just with nothing
+        assert!(
+            canteens
+                .iter()
+                .any(|canteen| canteen.rating == Rating::default())
+        );
+    }
+}
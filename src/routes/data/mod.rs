@@ -1,8 +1,11 @@
 mod all;
+mod top_stores;
 use axum::{Router, routing::get};
 
 use crate::state::AppState;
 
 pub(super) fn make_router() -> Router<AppState> {
-    Router::new().route("/", get(all::handle))
+    Router::new()
+        .route("/", get(all::handle))
+        .route("/stores/top", get(top_stores::handle))
 }
@@ -0,0 +1,304 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
+use crate::{models::Rating, state::AppState};
+
+/// Handler for the best rated stores, optionally in one canteen or of one
+/// cuisine
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    Query(filters): Query<TopStoresFilters>,
+) -> impl IntoResponse {
+    let prior = Prior {
+        weight: f64::from(state.ranking_prior_weight()),
+        score: state.ranking_prior_score(),
+    };
+
+    match top_stores(state.db(), &filters, prior).await {
+        Ok(stores) => (StatusCode::OK, Json(stores)).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct TopStoresFilters {
+    canteen_id: Option<i64>,
+    cuisine: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RankedStore {
+    id: i64,
+    name: String,
+    canteen_id: i64,
+    cuisine: String,
+    image_url: String,
+    rating: Rating,
+    /// Bayesian average score the store is ranked by.
+    weighted_score: f64,
+}
+
+/// Imaginary reviews every store starts out with, so that a store needs many
+/// good reviews, not just a few, to rank highly
+#[derive(Debug, Clone, Copy)]
+struct Prior {
+    weight: f64,
+    /// `None` for the average score of all stores, whichever are ranked.
+    score: Option<f64>,
+}
+
+/// Number of reviews giving a store a score
+struct DbStoreScoreCount {
+    store_id: i64,
+    store_name: String,
+    canteen_id: i64,
+    cuisine: String,
+    image_url: String,
+    score: i64,
+    review_count: i64,
+}
+
+async fn top_stores(
+    db: &MySqlPool,
+    filters: &TopStoresFilters,
+    prior: Prior,
+) -> Result<Vec<RankedStore>, (StatusCode, &'static str)> {
+    let limit = validate_limit(filters.limit);
+    let score_counts = fetch_store_score_counts(db, filters).await?;
+
+    let stores = collect_stores(score_counts);
+
+    // Taken over every store, as the few stores in a canteen or of a cuisine
+    // would otherwise only be measured against themselves
+    let prior_score = match prior.score {
+        Some(score) => score,
+        None => fetch_average_score(db).await?.unwrap_or(3.0),
+    };
+    let stores = rank_stores(stores, prior_score, prior.weight);
+
+    Ok(stores
+        .into_iter()
+        .take(usize::try_from(limit).unwrap_or_default())
+        .collect())
+}
+
+fn validate_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(10).clamp(1, 100)
+}
+
+/// Gather the score counts of each store, which come in grouped by store,
+/// into its rating.
+fn collect_stores(score_counts: Vec<DbStoreScoreCount>) -> Vec<RankedStore> {
+    let mut stores: Vec<RankedStore> = Vec::new();
+
+    for score_count in score_counts {
+        if stores
+            .last()
+            .is_none_or(|store| store.id != score_count.store_id)
+        {
+            stores.push(RankedStore {
+                id: score_count.store_id,
+                name: score_count.store_name,
+                canteen_id: score_count.canteen_id,
+                cuisine: score_count.cuisine,
+                image_url: score_count.image_url,
+                rating: Rating::default(),
+                weighted_score: 0.0,
+            });
+        }
+        if let Some(store) = stores.last_mut() {
+            store
+                .rating
+                .add(score_count.score, score_count.review_count);
+        }
+    }
+
+    stores
+}
+
+/// Best first. Ties go to the store with more reviews.
+fn rank_stores(stores: Vec<RankedStore>, prior_score: f64, prior_weight: f64) -> Vec<RankedStore> {
+    let mut stores: Vec<RankedStore> = stores
+        .into_iter()
+        .map(|store| RankedStore {
+            weighted_score: store.rating.weighted_score(prior_score, prior_weight),
+            ..store
+        })
+        .collect();
+
+    stores.sort_by(|a, b| {
+        b.weighted_score
+            .total_cmp(&a.weighted_score)
+            .then(b.rating.review_count.cmp(&a.rating.review_count))
+            .then(a.id.cmp(&b.id))
+    });
+    stores
+}
+
+/// Average score of reviews of stores as a whole, across all stores.
+async fn fetch_average_score(db: &MySqlPool) -> Result<Option<f64>, (StatusCode, &'static str)> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT CAST(AVG(score) AS DOUBLE) AS average_score
+        FROM review
+        WHERE item_id IS NULL
+        "#
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))
+}
+
+/// Only reviews of stores as a whole count, and stores without any are left
+/// out.
+async fn fetch_store_score_counts(
+    db: &MySqlPool,
+    filters: &TopStoresFilters,
+) -> Result<Vec<DbStoreScoreCount>, (StatusCode, &'static str)> {
+    sqlx::query_as!(
+        DbStoreScoreCount,
+        r#"
+        SELECT
+            store.store_id,
+            store.store_name,
+            store.canteen_id,
+            store.cuisine,
+            store.image_url,
+            review.score,
+            COUNT(*) AS review_count
+        FROM store
+        JOIN review ON review.store_id = store.store_id AND review.item_id IS NULL
+        WHERE (? IS NULL OR store.canteen_id = ?)
+            AND (? IS NULL OR store.cuisine = ?)
+        GROUP BY store.store_id, review.score
+        ORDER BY store.store_id
+        "#,
+        filters.canteen_id,
+        filters.canteen_id,
+        filters.cuisine,
+        filters.cuisine
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(canteen_id: Option<i64>, cuisine: Option<&str>) -> TopStoresFilters {
+        TopStoresFilters {
+            canteen_id,
+            cuisine: cuisine.map(str::to_string),
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_limit() {
+        assert_eq!(validate_limit(None), 10);
+        assert_eq!(validate_limit(Some(25)), 25);
+        assert_eq!(validate_limit(Some(150)), 100);
+        assert_eq!(validate_limit(Some(0)), 1);
+    }
+
+    #[test]
+    fn test_rank_stores() {
+        let store = |id, histogram| RankedStore {
+            id,
+            name: format!("Store {id}"),
+            canteen_id: 1,
+            cuisine: "Chinese".to_string(),
+            image_url: String::new(),
+            rating: Rating::from_histogram(histogram),
+            weighted_score: 0.0,
+        };
+        let stores = vec![
+            store(1, [0, 0, 0, 0, 1]),
+            store(2, [0, 0, 0, 60, 140]),
+            store(3, [0, 0, 0, 0, 2]),
+            store(4, [0, 0, 0, 0, 1]),
+        ];
+
+        // One 5-star review is no match for 200 reviews averaging 4.7
+        let ranked: Vec<i64> = rank_stores(stores, 3.5, 10.0)
+            .iter()
+            .map(|store| store.id)
+            .collect();
+        assert_eq!(ranked, [2, 3, 1, 4]);
+    }
+
+    #[sqlx::test]
+    async fn test_top_stores(db: MySqlPool) {
+        for i in 1..=3 {
+            sqlx::query!(
+                r#"INSERT INTO nomer (display_name, email, password_hash) 
+                   VALUES (?, ?, ?)"#,
+                format!("Test User {}", i),
+                format!("test{}@test.com", i),
+                format!("test_hash_{}", i)
+            )
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        // Stores 1 (Chinese) and 2 (Muslim) are in canteen 1, and store 7
+        // (Chinese) in canteen 2. Item reviews do not count.
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, item_id, nomer_id, score, comment)
+               VALUES (1, NULL, 1, 5, 'Great'), (1, NULL, 2, 5, 'Great'),
+                      (1, NULL, 3, 4, 'Good'), (2, NULL, 1, 5, 'Great'),
+                      (2, 3, 1, 1, 'Awful'), (7, NULL, 1, 2, 'Poor')"#
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let prior = Prior {
+            weight: 2.0,
+            score: None,
+        };
+        let stores = top_stores(&db, &filters(None, None), prior).await.unwrap();
+        let ranked: Vec<i64> = stores.iter().map(|store| store.id).collect();
+        assert_eq!(ranked, [1, 2, 7]);
+        assert_eq!(stores[1].rating.histogram, [0, 0, 0, 0, 1]);
+
+        let stores = top_stores(&db, &filters(Some(1), None), prior)
+            .await
+            .unwrap();
+        assert!(stores.iter().all(|store| store.canteen_id == 1));
+        assert_eq!(stores.len(), 2);
+
+        let stores = top_stores(&db, &filters(None, Some("Chinese")), prior)
+            .await
+            .unwrap();
+        let ranked: Vec<i64> = stores.iter().map(|store| store.id).collect();
+        assert_eq!(ranked, [1, 7]);
+
+        // The prior is the average of all stores, at 4.2, even when a single
+        // store is ranked
+        let stores = top_stores(&db, &filters(None, Some("Muslim")), prior)
+            .await
+            .unwrap();
+        assert_eq!(stores.len(), 1);
+        assert!((stores[0].weighted_score - (4.2 * 2.0 + 5.0) / 3.0).abs() < 1e-9);
+
+        // A generous enough prior puts the single 5-star review on top
+        let prior = Prior {
+            weight: 2.0,
+            score: Some(5.0),
+        };
+        let stores = top_stores(&db, &filters(None, None), prior).await.unwrap();
+        assert_eq!(stores[0].id, 2);
+    }
+}
@@ -2,6 +2,7 @@ mod data;
 mod review;
 mod session;
 mod user;
+mod well_known;
 
 use axum::Router;
 
@@ -13,4 +14,5 @@ pub(super) fn make_router() -> Router<AppState> {
         .nest("/session", session::make_router())
         .nest("/data", data::make_router())
         .nest("/review", review::make_router())
+        .nest("/.well-known", well_known::make_router())
 }
@@ -2,6 +2,7 @@ use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use chrono::Utc;
 use serde::Deserialize;
 use sqlx::MySqlPool;
+use tracing::error;
 
 use super::DbReview;
 use crate::{
@@ -14,9 +15,14 @@ pub(super) async fn handle(
     nomer: Nomer,
     body: Json<CreateReviewRequest>,
 ) -> impl IntoResponse {
+    if !nomer.is_verified() {
+        return (StatusCode::FORBIDDEN, "Email not verified").into_response();
+    }
+
     match create_review(
         state.db(),
         body.store_id,
+        body.item_id,
         nomer.id,
         body.score,
         body.comment.clone(),
@@ -32,26 +38,32 @@ pub(super) async fn handle(
 #[serde(rename_all = "camelCase")]
 pub(super) struct CreateReviewRequest {
     store_id: i64,
+    /// Set to review a single item of the store rather than the store.
+    item_id: Option<i64>,
     score: i64,
     comment: String,
 }
 
-async fn create_review(
+pub(super) async fn create_review(
     db: &MySqlPool,
     store_id: i64,
+    item_id: Option<i64>,
     nomer_id: i64,
     score: i64,
     comment: String,
 ) -> Result<Review, (StatusCode, &'static str)> {
     validate_review_input(store_id, nomer_id, score, &comment)?;
+    if let Some(item_id) = item_id {
+        validate_review_item(db, store_id, item_id).await?;
+    }
 
-    let review_id = insert_review(db, store_id, nomer_id, score, comment).await?;
+    let review_id = insert_review(db, store_id, item_id, nomer_id, score, comment).await?;
     let db_review = fetch_created_review(db, review_id).await?;
 
     Ok(db_review.into())
 }
 
-fn validate_review_input(
+pub(super) fn validate_review_input(
     store_id: i64,
     nomer_id: i64,
     score: i64,
@@ -80,9 +92,38 @@ fn validate_review_input(
     Ok(())
 }
 
+/// Check that the item reviewed is sold by the store reviewed.
+async fn validate_review_item(
+    db: &MySqlPool,
+    store_id: i64,
+    item_id: i64,
+) -> Result<(), (StatusCode, &'static str)> {
+    let item_store_id = sqlx::query_scalar!(
+        r#"
+        SELECT store_id AS "store_id: i64"
+        FROM item
+        WHERE item_id = ?
+        "#,
+        item_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        error!("Database error while fetching item to review: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch item")
+    })?;
+
+    match item_store_id {
+        Some(item_store_id) if item_store_id == store_id => Ok(()),
+        Some(_) => Err((StatusCode::BAD_REQUEST, "Item is not sold by this store")),
+        None => Err((StatusCode::BAD_REQUEST, "Invalid item ID")),
+    }
+}
+
 async fn insert_review(
     db: &MySqlPool,
     store_id: i64,
+    item_id: Option<i64>,
     nomer_id: i64,
     score: i64,
     comment: String,
@@ -91,10 +132,11 @@ async fn insert_review(
 
     let result = sqlx::query!(
         r#"
-        INSERT INTO review (store_id, nomer_id, score, comment, created_at)
-        VALUES (?, ?, ?, ?, ?)
+        INSERT INTO review (store_id, item_id, nomer_id, score, comment, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
         "#,
         store_id,
+        item_id,
         nomer_id,
         score,
         comment,
@@ -106,6 +148,13 @@ async fn insert_review(
         sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
             (StatusCode::BAD_REQUEST, "Invalid store or user ID")
         }
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            if item_id.is_some() {
+                (StatusCode::CONFLICT, "You have already reviewed this item")
+            } else {
+                (StatusCode::CONFLICT, "You have already reviewed this store")
+            }
+        }
         _ => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create review"),
     })?;
 
@@ -127,7 +176,8 @@ async fn fetch_created_review(
     sqlx::query_as!(
         DbReview,
         r#"
-        SELECT review_id, score, comment, nomer_id, store_id, created_at
+        SELECT review_id, score, comment, nomer_id AS "nomer_id: i64", store_id,
+            item_id AS "item_id: i64", created_at, updated_at
         FROM review
         WHERE review_id = ?
         "#,
@@ -237,7 +287,7 @@ mod tests {
     async fn test_insert_review_success(db: MySqlPool) {
         setup_test_data(&db).await;
 
-        let result = insert_review(&db, 1, 1, 4, "Great food!".to_string()).await;
+        let result = insert_review(&db, 1, None, 1, 4, "Great food!".to_string()).await;
         assert!(result.is_ok());
 
         let review_id = result.unwrap();
@@ -250,7 +300,7 @@ mod tests {
             .unwrap();
 
         assert_eq!(review.store_id, 1);
-        assert_eq!(review.nomer_id, 1);
+        assert_eq!(review.nomer_id, Some(1));
         assert_eq!(review.score, 4);
         assert_eq!(review.comment, "Great food!");
     }
@@ -260,14 +310,14 @@ mod tests {
         setup_test_data(&db).await;
 
         // Test with non-existent store_id
-        let result = insert_review(&db, 999, 1, 4, "Test".to_string()).await;
+        let result = insert_review(&db, 999, None, 1, 4, "Test".to_string()).await;
         assert!(result.is_err());
         let (status, message) = result.unwrap_err();
         assert_eq!(status, StatusCode::BAD_REQUEST);
         assert_eq!(message, "Invalid store or user ID");
 
         // Test with non-existent nomer_id
-        let result = insert_review(&db, 1, 999, 4, "Test".to_string()).await;
+        let result = insert_review(&db, 1, None, 999, 4, "Test".to_string()).await;
         assert!(result.is_err());
         let (status, message) = result.unwrap_err();
         assert_eq!(status, StatusCode::BAD_REQUEST);
@@ -279,7 +329,7 @@ mod tests {
         setup_test_data(&db).await;
 
         // First insert a review
-        let review_id = insert_review(&db, 1, 1, 5, "Excellent!".to_string())
+        let review_id = insert_review(&db, 1, None, 1, 5, "Excellent!".to_string())
             .await
             .unwrap();
 
@@ -290,7 +340,7 @@ mod tests {
         let db_review = result.unwrap();
         assert_eq!(db_review.review_id, review_id);
         assert_eq!(db_review.store_id, 1);
-        assert_eq!(db_review.nomer_id, 1);
+        assert_eq!(db_review.nomer_id, Some(1));
         assert_eq!(db_review.score, 5);
         assert_eq!(db_review.comment, "Excellent!");
     }
@@ -310,12 +360,12 @@ mod tests {
     async fn test_create_review_success(db: MySqlPool) {
         setup_test_data(&db).await;
 
-        let result = create_review(&db, 1, 1, 4, "Delicious food!".to_string()).await;
+        let result = create_review(&db, 1, None, 1, 4, "Delicious food!".to_string()).await;
         assert!(result.is_ok());
 
         let review = result.unwrap();
         assert_eq!(review.store_id, 1);
-        assert_eq!(review.nomer_id, 1);
+        assert_eq!(review.nomer_id, Some(1));
         assert_eq!(review.score, 4);
         assert_eq!(review.comment, "Delicious food!");
         assert!(review.id > 0);
@@ -331,19 +381,19 @@ mod tests {
         setup_test_data(&db).await;
 
         // Test invalid store_id
-        let result = create_review(&db, 0, 1, 4, "Test".to_string()).await;
+        let result = create_review(&db, 0, None, 1, 4, "Test".to_string()).await;
         assert!(result.is_err());
         let (status, _) = result.unwrap_err();
         assert_eq!(status, StatusCode::BAD_REQUEST);
 
         // Test invalid score
-        let result = create_review(&db, 1, 1, 0, "Test".to_string()).await;
+        let result = create_review(&db, 1, None, 1, 0, "Test".to_string()).await;
         assert!(result.is_err());
         let (status, _) = result.unwrap_err();
         assert_eq!(status, StatusCode::BAD_REQUEST);
 
         // Test empty comment
-        let result = create_review(&db, 1, 1, 4, String::new()).await;
+        let result = create_review(&db, 1, None, 1, 4, String::new()).await;
         assert!(result.is_err());
         let (status, _) = result.unwrap_err();
         assert_eq!(status, StatusCode::BAD_REQUEST);
@@ -354,7 +404,7 @@ mod tests {
         setup_test_data(&db).await;
 
         let special_comment = "Great food! Special chars: !@#$%^&*()_+{}|:<>?[]\\;',./";
-        let result = create_review(&db, 1, 1, 5, special_comment.to_string()).await;
+        let result = create_review(&db, 1, None, 1, 5, special_comment.to_string()).await;
         assert!(result.is_ok());
 
         let review = result.unwrap();
@@ -366,7 +416,7 @@ mod tests {
         setup_test_data(&db).await;
 
         let unicode_comment = "美食! Delicious! 맛있어요! 🍜 🌟";
-        let result = create_review(&db, 1, 1, 5, unicode_comment.to_string()).await;
+        let result = create_review(&db, 1, None, 1, 5, unicode_comment.to_string()).await;
         assert!(result.is_ok());
 
         let review = result.unwrap();
@@ -378,13 +428,13 @@ mod tests {
         setup_test_data(&db).await;
 
         // Test minimum score
-        let result = create_review(&db, 1, 1, 1, "Poor".to_string()).await;
+        let result = create_review(&db, 1, None, 1, 1, "Poor".to_string()).await;
         assert!(result.is_ok());
         let review = result.unwrap();
         assert_eq!(review.score, 1);
 
         // Test maximum score
-        let result = create_review(&db, 1, 2, 5, "Excellent".to_string()).await;
+        let result = create_review(&db, 1, None, 2, 5, "Excellent".to_string()).await;
         assert!(result.is_ok());
         let review = result.unwrap();
         assert_eq!(review.score, 5);
@@ -395,7 +445,7 @@ mod tests {
         setup_test_data(&db).await;
 
         let max_comment = "x".repeat(255);
-        let result = create_review(&db, 1, 1, 3, max_comment.clone()).await;
+        let result = create_review(&db, 1, None, 1, 3, max_comment.clone()).await;
         assert!(result.is_ok());
 
         let review = result.unwrap();
@@ -408,19 +458,73 @@ mod tests {
         setup_test_data(&db).await;
 
         // Create first review
-        let result1 = create_review(&db, 1, 1, 3, "First review".to_string()).await;
+        let result1 = create_review(&db, 1, None, 1, 3, "First review".to_string()).await;
         assert!(result1.is_ok());
         let review1 = result1.unwrap();
 
-        // Create second review from same user for same store
-        let result2 = create_review(&db, 1, 1, 5, "Updated review".to_string()).await;
-        assert!(result2.is_ok());
-        let review2 = result2.unwrap();
+        // A second review from the same user for the same store is refused
+        let result2 = create_review(&db, 1, None, 1, 5, "Updated review".to_string()).await;
+        assert_eq!(
+            result2.unwrap_err(),
+            (StatusCode::CONFLICT, "You have already reviewed this store")
+        );
+
+        // The first review is left as it was
+        let review = fetch_created_review(&db, review1.id).await.unwrap();
+        assert_eq!(review.score, 3);
+        assert_eq!(review.comment, "First review");
+
+        // Other stores can still be reviewed
+        let result3 = create_review(&db, 2, None, 1, 5, "Another store".to_string()).await;
+        assert!(result3.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_create_item_review(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        // Items 1 and 2 are sold by store 1, items 3 and 4 by store 2
+        let review = create_review(&db, 1, Some(2), 1, 5, "Tender beef".to_string())
+            .await
+            .unwrap();
+        assert_eq!(review.store_id, 1);
+        assert_eq!(review.item_id, Some(2));
+
+        // Items and the store itself are reviewed separately
+        assert!(
+            create_review(&db, 1, Some(1), 1, 2, "Too sweet".to_string())
+                .await
+                .is_ok()
+        );
+        assert!(
+            create_review(&db, 1, None, 1, 4, "Good overall".to_string())
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            create_review(&db, 1, Some(2), 1, 1, "Changed my mind".to_string())
+                .await
+                .unwrap_err(),
+            (StatusCode::CONFLICT, "You have already reviewed this item")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_create_item_review_invalid_item(db: MySqlPool) {
+        setup_test_data(&db).await;
 
-        // Both reviews should exist and have different IDs
-        assert_ne!(review1.id, review2.id);
-        assert_eq!(review1.store_id, review2.store_id);
-        assert_eq!(review1.nomer_id, review2.nomer_id);
+        assert_eq!(
+            create_review(&db, 1, Some(3), 1, 5, "Wrong store".to_string())
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Item is not sold by this store")
+        );
+        assert_eq!(
+            create_review(&db, 1, Some(9999), 1, 5, "No such item".to_string())
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Invalid item ID")
+        );
     }
 
     #[sqlx::test]
@@ -428,7 +532,7 @@ mod tests {
         setup_test_data(&db).await;
 
         let before_creation = chrono::Utc::now();
-        let result = create_review(&db, 1, 1, 4, "Time test".to_string()).await;
+        let result = create_review(&db, 1, None, 1, 4, "Time test".to_string()).await;
         let after_creation = chrono::Utc::now() + chrono::Duration::seconds(5); // Add buffer for DB operations
 
         assert!(result.is_ok());
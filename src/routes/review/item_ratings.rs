@@ -0,0 +1,103 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Serialize;
+use sqlx::MySqlPool;
+
+use crate::state::AppState;
+
+/// Handler for the ratings of each item a store sells
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    Path(store_id): Path<i64>,
+) -> impl IntoResponse {
+    match fetch_item_ratings(state.db(), store_id).await {
+        Ok(ratings) => (StatusCode::OK, Json(ratings)).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ItemRating {
+    item_id: i64,
+    review_count: i64,
+    /// `None` if the item has not been reviewed yet.
+    average_score: Option<f64>,
+}
+
+/// Items without reviews are included, so that every item of the store is
+/// listed.
+async fn fetch_item_ratings(
+    db: &MySqlPool,
+    store_id: i64,
+) -> Result<Vec<ItemRating>, (StatusCode, &'static str)> {
+    sqlx::query_as!(
+        ItemRating,
+        r#"
+        SELECT item.item_id, COUNT(review.review_id) AS review_count,
+            CAST(AVG(review.score) AS DOUBLE) AS average_score
+        FROM item
+        LEFT JOIN review ON review.item_id = item.item_id
+        WHERE item.store_id = ?
+        GROUP BY item.item_id
+        ORDER BY item.item_id
+        "#,
+        store_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch item ratings",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_fetch_item_ratings(db: MySqlPool) {
+        for i in 1..=2 {
+            sqlx::query!(
+                r#"INSERT INTO nomer (display_name, email, password_hash) 
+                   VALUES (?, ?, ?)"#,
+                format!("Test User {}", i),
+                format!("test{}@test.com", i),
+                format!("test_hash_{}", i)
+            )
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        // Items 1 and 2 are sold by store 1, item 3 by store 2
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, item_id, nomer_id, score, comment)
+               VALUES (1, NULL, 1, 1, 'Store'), (1, 1, 1, 5, 'Great'),
+                      (1, 1, 2, 2, 'Meh'), (2, 3, 1, 4, 'Other store')"#
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let ratings = fetch_item_ratings(&db, 1).await.unwrap();
+        assert_eq!(ratings.len(), 2);
+
+        assert_eq!(ratings[0].item_id, 1);
+        assert_eq!(ratings[0].review_count, 2);
+        assert_eq!(ratings[0].average_score, Some(3.5));
+
+        assert_eq!(ratings[1].item_id, 2);
+        assert_eq!(ratings[1].review_count, 0);
+        assert_eq!(ratings[1].average_score, None);
+
+        assert!(fetch_item_ratings(&db, 999).await.unwrap().is_empty());
+    }
+}
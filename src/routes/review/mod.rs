@@ -1,10 +1,13 @@
 mod create;
+mod item_ratings;
 mod read_many;
 mod read_one;
 mod remove;
+mod update;
+mod upsert;
 use axum::{
     Router,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post, put},
 };
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -16,7 +19,10 @@ pub(super) fn make_router() -> Router<AppState> {
         .route("/", post(create::handle))
         .route("/", get(read_many::handle))
         .route("/{id}", get(read_one::handle))
+        .route("/{id}", patch(update::handle))
         .route("/{id}", delete(remove::handle))
+        .route("/store/{store_id}", put(upsert::handle))
+        .route("/store/{store_id}/items", get(item_ratings::handle))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,9 +31,11 @@ struct DbReview {
     review_id: i64,
     score: i64,
     comment: String,
-    nomer_id: i64,
+    nomer_id: Option<i64>,
     store_id: i64,
+    item_id: Option<i64>,
     created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
 }
 
 impl From<DbReview> for crate::models::Review {
@@ -35,10 +43,12 @@ impl From<DbReview> for crate::models::Review {
         Self {
             id: db_review.review_id,
             store_id: db_review.store_id,
+            item_id: db_review.item_id,
             nomer_id: db_review.nomer_id,
             score: db_review.score,
             comment: db_review.comment,
             created_at: db_review.created_at,
+            updated_at: db_review.updated_at,
         }
     }
 }
@@ -48,10 +58,12 @@ impl From<crate::models::Review> for DbReview {
         Self {
             review_id: review.id,
             store_id: review.store_id,
+            item_id: review.item_id,
             nomer_id: review.nomer_id,
             score: review.score,
             comment: review.comment,
             created_at: review.created_at,
+            updated_at: review.updated_at,
         }
     }
 }
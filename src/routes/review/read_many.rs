@@ -14,6 +14,7 @@ use crate::{models::Review, state::AppState};
 pub struct ReviewFilters {
     pub nomer_id: Option<i64>,
     pub store_id: Option<i64>,
+    pub item_id: Option<i64>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
 }
@@ -35,6 +36,21 @@ async fn read_many_reviews(
     let limit = validate_limit(filters.limit);
     let offset = filters.offset.unwrap_or(0);
 
+    if let Some(item_id) = filters.item_id {
+        let db_reviews = fetch_reviews_by_item(
+            db,
+            item_id,
+            filters.nomer_id,
+            filters.store_id,
+            limit,
+            offset,
+        )
+        .await?;
+        return Ok(db_reviews.into_iter().map(Into::into).collect());
+    }
+
+    // Filtering by store alone lists the reviews of the store as a whole,
+    // the same ones its rating counts; its items' reviews are listed by item
     let db_reviews = match (filters.nomer_id, filters.store_id) {
         (Some(nomer_id), Some(store_id)) => {
             fetch_reviews_with_both_filters(db, nomer_id, store_id, limit, offset).await
@@ -61,9 +77,10 @@ async fn fetch_reviews_with_both_filters(
     sqlx::query_as!(
         DbReview,
         r#"
-        SELECT review_id, store_id, nomer_id, score, comment, created_at
+        SELECT review_id, store_id, nomer_id AS "nomer_id: i64", item_id AS "item_id: i64",
+            score, comment, created_at, updated_at
         FROM review
-        WHERE nomer_id = ? AND store_id = ?
+        WHERE nomer_id = ? AND store_id = ? AND item_id IS NULL
         ORDER BY created_at DESC
         LIMIT ? OFFSET ?
         "#,
@@ -86,7 +103,8 @@ async fn fetch_reviews_by_nomer(
     sqlx::query_as!(
         DbReview,
         r#"
-        SELECT review_id, store_id, nomer_id, score, comment, created_at
+        SELECT review_id, store_id, nomer_id AS "nomer_id: i64", item_id AS "item_id: i64",
+            score, comment, created_at, updated_at
         FROM review
         WHERE nomer_id = ?
         ORDER BY created_at DESC
@@ -110,12 +128,48 @@ async fn fetch_reviews_by_store(
     sqlx::query_as!(
         DbReview,
         r#"
-        SELECT review_id, store_id, nomer_id, score, comment, created_at
+        SELECT review_id, store_id, nomer_id AS "nomer_id: i64", item_id AS "item_id: i64",
+            score, comment, created_at, updated_at
+        FROM review
+        WHERE store_id = ? AND item_id IS NULL
+        ORDER BY created_at DESC
+        LIMIT ? OFFSET ?
+        "#,
+        store_id,
+        limit,
+        offset
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch reviews"))
+}
+
+/// The other filters are optional here, as an item only ever has a few
+/// reviews to narrow down.
+async fn fetch_reviews_by_item(
+    db: &MySqlPool,
+    item_id: i64,
+    nomer_id: Option<i64>,
+    store_id: Option<i64>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<DbReview>, (StatusCode, &'static str)> {
+    sqlx::query_as!(
+        DbReview,
+        r#"
+        SELECT review_id, store_id, nomer_id AS "nomer_id: i64", item_id AS "item_id: i64",
+            score, comment, created_at, updated_at
         FROM review
-        WHERE store_id = ?
+        WHERE item_id = ?
+            AND (? IS NULL OR nomer_id = ?)
+            AND (? IS NULL OR store_id = ?)
         ORDER BY created_at DESC
         LIMIT ? OFFSET ?
         "#,
+        item_id,
+        nomer_id,
+        nomer_id,
+        store_id,
         store_id,
         limit,
         offset
@@ -133,7 +187,8 @@ async fn fetch_all_reviews(
     sqlx::query_as!(
         DbReview,
         r#"
-        SELECT review_id, store_id, nomer_id, score, comment, created_at
+        SELECT review_id, store_id, nomer_id AS "nomer_id: i64", item_id AS "item_id: i64",
+            score, comment, created_at, updated_at
         FROM review
         ORDER BY created_at DESC
         LIMIT ? OFFSET ?
@@ -199,8 +254,8 @@ mod tests {
         let reviews = fetch_reviews_by_nomer(&db, 1, 10, 0).await.unwrap();
 
         assert_eq!(reviews.len(), 2);
-        assert_eq!(reviews[0].nomer_id, 1);
-        assert_eq!(reviews[1].nomer_id, 1);
+        assert_eq!(reviews[0].nomer_id, Some(1));
+        assert_eq!(reviews[1].nomer_id, Some(1));
     }
 
     #[sqlx::test]
@@ -208,18 +263,23 @@ mod tests {
         setup_test_data(&db).await;
 
         sqlx::query!(
-            r#"INSERT INTO review (store_id, nomer_id, score, comment) 
-               VALUES (1, 1, 5, 'Store 1 Review 1'), (1, 2, 4, 'Store 1 Review 2'), (2, 3, 3, 'Store 2 Review')"#
+            r#"INSERT INTO review (store_id, item_id, nomer_id, score, comment)
+               VALUES (1, NULL, 1, 5, 'Store 1 Review 1'), (1, NULL, 2, 4, 'Store 1 Review 2'),
+                      (1, 1, 1, 2, 'Item 1 Review'), (2, NULL, 3, 3, 'Store 2 Review')"#
         )
         .execute(&db)
         .await
         .unwrap();
 
+        // Item reviews are left out, as they are from the store's rating
         let reviews = fetch_reviews_by_store(&db, 1, 10, 0).await.unwrap();
 
         assert_eq!(reviews.len(), 2);
-        assert_eq!(reviews[0].store_id, 1);
-        assert_eq!(reviews[1].store_id, 1);
+        assert!(
+            reviews
+                .iter()
+                .all(|r| r.store_id == 1 && r.item_id.is_none())
+        );
     }
 
     #[sqlx::test]
@@ -240,7 +300,7 @@ mod tests {
             .unwrap();
 
         assert_eq!(reviews.len(), 1);
-        assert_eq!(reviews[0].nomer_id, 1);
+        assert_eq!(reviews[0].nomer_id, Some(1));
         assert_eq!(reviews[0].store_id, 1);
         assert_eq!(reviews[0].comment, "Match");
     }
@@ -252,8 +312,9 @@ mod tests {
         for i in 1..=5 {
             sqlx::query!(
                 r#"INSERT INTO review (store_id, nomer_id, score, comment) 
-                   VALUES (?, 1, 5, ?)"#,
-                i.min(3),
+                   VALUES (?, ?, 5, ?)"#,
+                (i - 1) % 3 + 1,
+                (i - 1) / 3 + 1,
                 format!("Review {}", i)
             )
             .execute(&db)
@@ -286,6 +347,7 @@ mod tests {
         let filters = ReviewFilters {
             nomer_id: None,
             store_id: None,
+            item_id: None,
             limit: None,
             offset: None,
         };
@@ -295,16 +357,18 @@ mod tests {
         let filters = ReviewFilters {
             nomer_id: Some(1),
             store_id: None,
+            item_id: None,
             limit: None,
             offset: None,
         };
         let reviews = read_many_reviews(&db, filters).await.unwrap();
         assert_eq!(reviews.len(), 1);
-        assert_eq!(reviews[0].nomer_id, 1);
+        assert_eq!(reviews[0].nomer_id, Some(1));
 
         let filters = ReviewFilters {
             nomer_id: None,
             store_id: Some(1),
+            item_id: None,
             limit: None,
             offset: None,
         };
@@ -315,17 +379,19 @@ mod tests {
         let filters = ReviewFilters {
             nomer_id: Some(2),
             store_id: Some(1),
+            item_id: None,
             limit: None,
             offset: None,
         };
         let reviews = read_many_reviews(&db, filters).await.unwrap();
         assert_eq!(reviews.len(), 1);
-        assert_eq!(reviews[0].nomer_id, 2);
+        assert_eq!(reviews[0].nomer_id, Some(2));
         assert_eq!(reviews[0].store_id, 1);
 
         let filters = ReviewFilters {
             nomer_id: None,
             store_id: None,
+            item_id: None,
             limit: Some(2),
             offset: None,
         };
@@ -335,6 +401,7 @@ mod tests {
         let filters = ReviewFilters {
             nomer_id: None,
             store_id: None,
+            item_id: None,
             limit: Some(2),
             offset: Some(1),
         };
@@ -342,6 +409,53 @@ mod tests {
         assert_eq!(reviews.len(), 2);
     }
 
+    #[sqlx::test]
+    async fn test_read_many_reviews_item_filter(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        // Items 1 and 2 are sold by store 1
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, item_id, nomer_id, score, comment)
+               VALUES (1, NULL, 1, 4, 'Store'), (1, 1, 1, 2, 'Item 1'),
+                      (1, 1, 2, 3, 'Item 1 again'), (1, 2, 1, 5, 'Item 2')"#
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let filters = ReviewFilters {
+            nomer_id: None,
+            store_id: None,
+            item_id: Some(1),
+            limit: None,
+            offset: None,
+        };
+        let reviews = read_many_reviews(&db, filters).await.unwrap();
+        assert_eq!(reviews.len(), 2);
+        assert!(reviews.iter().all(|r| r.item_id == Some(1)));
+
+        let filters = ReviewFilters {
+            nomer_id: Some(2),
+            store_id: Some(1),
+            item_id: Some(1),
+            limit: None,
+            offset: None,
+        };
+        let reviews = read_many_reviews(&db, filters).await.unwrap();
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(reviews[0].comment, "Item 1 again");
+
+        let filters = ReviewFilters {
+            nomer_id: None,
+            store_id: Some(2),
+            item_id: Some(1),
+            limit: None,
+            offset: None,
+        };
+        let reviews = read_many_reviews(&db, filters).await.unwrap();
+        assert!(reviews.is_empty());
+    }
+
     async fn setup_test_data(db: &MySqlPool) {
         sqlx::query!(
             r#"INSERT INTO canteen (canteen_name, latitude, longitude, image_url) 
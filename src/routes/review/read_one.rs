@@ -27,14 +27,15 @@ async fn read_one_review(
     Ok(db_review.into())
 }
 
-async fn fetch_review_by_id(
+pub(super) async fn fetch_review_by_id(
     db: &MySqlPool,
     review_id: i64,
 ) -> Result<DbReview, (StatusCode, &'static str)> {
     sqlx::query_as!(
         DbReview,
         r#"
-        SELECT review_id, store_id, nomer_id, score, comment, created_at
+        SELECT review_id, store_id, nomer_id AS "nomer_id: i64", item_id AS "item_id: i64",
+            score, comment, created_at, updated_at
         FROM review
         WHERE review_id = ?
         "#,
@@ -82,7 +83,7 @@ mod tests {
         let review = result.unwrap();
         assert_eq!(review.review_id, review_id);
         assert_eq!(review.store_id, 1);
-        assert_eq!(review.nomer_id, 1);
+        assert_eq!(review.nomer_id, Some(1));
         assert_eq!(review.score, 5);
         assert_eq!(review.comment, "Test review");
     }
@@ -127,7 +128,7 @@ mod tests {
         let review = result.unwrap();
         assert_eq!(review.id, review_id);
         assert_eq!(review.store_id, 2);
-        assert_eq!(review.nomer_id, 2);
+        assert_eq!(review.nomer_id, Some(2));
         assert_eq!(review.score, 4);
         assert_eq!(review.comment, "Another test review");
     }
@@ -184,7 +185,7 @@ mod tests {
             let review = read_one_review(&db, review_id).await.unwrap();
             assert_eq!(review.id, review_id);
             assert_eq!(review.store_id, i);
-            assert_eq!(review.nomer_id, i);
+            assert_eq!(review.nomer_id, Some(i));
             assert_eq!(review.score, i + 2);
             assert_eq!(review.comment, comment);
         }
@@ -277,7 +278,7 @@ mod tests {
         // Test maximum score
         sqlx::query!(
             r#"INSERT INTO review (store_id, nomer_id, score, comment) 
-               VALUES (1, 1, 5, 'Maximum score')"#
+               VALUES (2, 1, 5, 'Maximum score')"#
         )
         .execute(&db)
         .await
@@ -320,7 +321,7 @@ mod tests {
 
         assert_eq!(review.id, review_id);
         assert_eq!(review.store_id, 1);
-        assert_eq!(review.nomer_id, 1);
+        assert_eq!(review.nomer_id, Some(1));
         assert_eq!(review.score, 4);
         assert_eq!(review.comment, "Conversion test");
         // created_at should be set automatically
@@ -32,7 +32,7 @@ async fn remove_review(
     Ok(())
 }
 
-async fn verify_review_ownership(
+pub(super) async fn verify_review_ownership(
     db: &MySqlPool,
     nomer_id: i64,
     review_id: i64,
@@ -56,7 +56,7 @@ async fn verify_review_ownership(
 
     match result {
         Some(row) => {
-            if i64::from(row.nomer_id) == nomer_id {
+            if row.nomer_id.map(i64::from) == Some(nomer_id) {
                 Ok(())
             } else {
                 Err((
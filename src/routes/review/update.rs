@@ -0,0 +1,300 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{Error, MySqlPool};
+use tracing::error;
+
+use super::{
+    create::validate_review_input, read_one::fetch_review_by_id, remove::verify_review_ownership,
+};
+use crate::{
+    models::{Nomer, Review},
+    state::AppState,
+};
+
+/// Handler for editing a review of the authenticated nomer
+///
+/// The version being replaced is kept as a revision.
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    nomer: Nomer,
+    Path(review_id): Path<i64>,
+    Json(body): Json<UpdateReviewRequest>,
+) -> impl IntoResponse {
+    if !nomer.is_verified() {
+        return (StatusCode::FORBIDDEN, "Email not verified").into_response();
+    }
+
+    match update_review(state.db(), nomer.id, review_id, body).await {
+        Ok(review) => (StatusCode::OK, Json(review)).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+/// Fields left out are kept as they are.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct UpdateReviewRequest {
+    pub(super) score: Option<i64>,
+    pub(super) comment: Option<String>,
+}
+
+pub(super) async fn update_review(
+    db: &MySqlPool,
+    nomer_id: i64,
+    review_id: i64,
+    body: UpdateReviewRequest,
+) -> Result<Review, (StatusCode, &'static str)> {
+    verify_review_ownership(db, nomer_id, review_id)
+        .await
+        .map_err(|(status, message)| match status {
+            StatusCode::FORBIDDEN => (status, "You can only edit your own reviews"),
+            _ => (status, message),
+        })?;
+
+    let db_error = |e: Error| {
+        error!("Database error while updating review: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update review")
+    };
+    let mut tx = db.begin().await.map_err(db_error)?;
+
+    // Lock the review so that concurrent edits each keep the right revision
+    let current = sqlx::query!(
+        r#"
+        SELECT store_id, score, comment, created_at, updated_at
+        FROM review
+        WHERE review_id = ?
+        FOR UPDATE
+        "#,
+        review_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let Some(current) = current else {
+        return Err((StatusCode::NOT_FOUND, "Review not found"));
+    };
+
+    let score = body.score.unwrap_or(i64::from(current.score));
+    let comment = body.comment.unwrap_or_else(|| current.comment.clone());
+    validate_review_input(i64::from(current.store_id), nomer_id, score, &comment)?;
+
+    // Nothing to record if nothing changed
+    if score != i64::from(current.score) || comment != current.comment {
+        sqlx::query!(
+            r#"
+            INSERT INTO review_revision (review_id, score, comment, written_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+            review_id,
+            current.score,
+            current.comment,
+            current.updated_at.unwrap_or(current.created_at)
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        sqlx::query!(
+            r#"
+            UPDATE review
+            SET score = ?, comment = ?, updated_at = ?
+            WHERE review_id = ?
+            "#,
+            score,
+            comment,
+            Utc::now().naive_utc(),
+            review_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(fetch_review_by_id(db, review_id).await?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_data(db: &MySqlPool) -> i64 {
+        sqlx::query!(
+            r#"INSERT INTO canteen (canteen_name, latitude, longitude, image_url) 
+               VALUES ('Test Canteen', 1.0, 103.0, 'test_canteen.jpg')"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        sqlx::query!(
+            r#"INSERT INTO store (store_name, is_open, cuisine, information, canteen_id, image_url)
+               VALUES ('Test Store', TRUE, 'Test Cuisine', 'Test Info', 1, 'test_store.jpg')"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        for i in 1..=2 {
+            sqlx::query!(
+                r#"INSERT INTO nomer (display_name, email, password_hash) 
+                   VALUES (?, ?, ?)"#,
+                format!("Test User {}", i),
+                format!("test{}@test.com", i),
+                format!("test_hash_{}", i)
+            )
+            .execute(db)
+            .await
+            .unwrap();
+        }
+
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, nomer_id, score, comment)
+               VALUES (1, 1, 2, 'Too salty')"#
+        )
+        .execute(db)
+        .await
+        .unwrap()
+        .last_insert_id()
+        .try_into()
+        .unwrap()
+    }
+
+    /// The score and comment of every revision of `review_id`, oldest first.
+    async fn revisions(db: &MySqlPool, review_id: i64) -> Vec<(i32, String)> {
+        sqlx::query!(
+            r#"
+            SELECT score, comment
+            FROM review_revision
+            WHERE review_id = ?
+            ORDER BY revision_id
+            "#,
+            review_id
+        )
+        .fetch_all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.score, row.comment))
+        .collect()
+    }
+
+    #[sqlx::test]
+    async fn test_update_review(db: MySqlPool) {
+        let review_id = setup_test_data(&db).await;
+        let original = fetch_review_by_id(&db, review_id).await.unwrap();
+        assert_eq!(original.updated_at, None);
+
+        let body = UpdateReviewRequest {
+            score: Some(4),
+            comment: Some("Less salty today".to_string()),
+        };
+        let review = update_review(&db, 1, review_id, body).await.unwrap();
+        assert_eq!(review.score, 4);
+        assert_eq!(review.comment, "Less salty today");
+        assert_eq!(review.created_at, original.created_at);
+        assert!(review.updated_at.is_some());
+
+        assert_eq!(
+            revisions(&db, review_id).await,
+            [(2, "Too salty".to_string())]
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_update_review_partial(db: MySqlPool) {
+        let review_id = setup_test_data(&db).await;
+
+        let body = UpdateReviewRequest {
+            comment: Some("Too salty, but cheap".to_string()),
+            ..Default::default()
+        };
+        let review = update_review(&db, 1, review_id, body).await.unwrap();
+        assert_eq!(review.score, 2);
+        assert_eq!(review.comment, "Too salty, but cheap");
+
+        let body = UpdateReviewRequest {
+            score: Some(3),
+            ..Default::default()
+        };
+        let review = update_review(&db, 1, review_id, body).await.unwrap();
+        assert_eq!(review.score, 3);
+        assert_eq!(review.comment, "Too salty, but cheap");
+
+        // Each edit keeps the version before it
+        assert_eq!(
+            revisions(&db, review_id).await,
+            [
+                (2, "Too salty".to_string()),
+                (2, "Too salty, but cheap".to_string())
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_update_review_unchanged(db: MySqlPool) {
+        let review_id = setup_test_data(&db).await;
+
+        let body = UpdateReviewRequest {
+            score: Some(2),
+            ..Default::default()
+        };
+        let review = update_review(&db, 1, review_id, body).await.unwrap();
+        assert_eq!(review.updated_at, None);
+        assert!(revisions(&db, review_id).await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_update_review_invalid(db: MySqlPool) {
+        let review_id = setup_test_data(&db).await;
+
+        let body = UpdateReviewRequest {
+            score: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_review(&db, 1, review_id, body).await.unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Score must be between 1 and 5")
+        );
+
+        let body = UpdateReviewRequest {
+            comment: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_review(&db, 1, review_id, body).await.unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Comment cannot be empty")
+        );
+
+        let review = fetch_review_by_id(&db, review_id).await.unwrap();
+        assert_eq!(review.score, 2);
+        assert!(revisions(&db, review_id).await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_update_review_not_owner(db: MySqlPool) {
+        let review_id = setup_test_data(&db).await;
+
+        let body = UpdateReviewRequest {
+            score: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_review(&db, 2, review_id, body).await.unwrap_err(),
+            (StatusCode::FORBIDDEN, "You can only edit your own reviews")
+        );
+
+        let body = UpdateReviewRequest::default();
+        assert_eq!(
+            update_review(&db, 1, 999, body).await.unwrap_err(),
+            (StatusCode::NOT_FOUND, "Review not found")
+        );
+    }
+}
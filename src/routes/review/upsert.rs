@@ -0,0 +1,242 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use tracing::error;
+
+use super::{
+    create::create_review,
+    update::{UpdateReviewRequest, update_review},
+};
+use crate::{
+    models::{Nomer, Review},
+    state::AppState,
+};
+
+/// Handler for setting the authenticated nomer's review of a store
+///
+/// Creates the review if there is none yet, or else edits it. The review is
+/// of the store as a whole, unless an item of it is given.
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    nomer: Nomer,
+    Path(store_id): Path<i64>,
+    Json(body): Json<UpsertReviewRequest>,
+) -> impl IntoResponse {
+    if !nomer.is_verified() {
+        return (StatusCode::FORBIDDEN, "Email not verified").into_response();
+    }
+
+    match upsert_review(
+        state.db(),
+        store_id,
+        body.item_id,
+        nomer.id,
+        body.score,
+        body.comment,
+    )
+    .await
+    {
+        Ok((review, true)) => (StatusCode::CREATED, Json(review)).into_response(),
+        Ok((review, false)) => (StatusCode::OK, Json(review)).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct UpsertReviewRequest {
+    item_id: Option<i64>,
+    score: i64,
+    comment: String,
+}
+
+/// Returns the review, and whether it was created rather than edited.
+async fn upsert_review(
+    db: &MySqlPool,
+    store_id: i64,
+    item_id: Option<i64>,
+    nomer_id: i64,
+    score: i64,
+    comment: String,
+) -> Result<(Review, bool), (StatusCode, &'static str)> {
+    // Trying to create first leaves it to the database to notice the review
+    // exists, even if another request has only just created it
+    match create_review(db, store_id, item_id, nomer_id, score, comment.clone()).await {
+        Ok(review) => return Ok((review, true)),
+        Err((StatusCode::CONFLICT, _)) => (),
+        Err(err) => return Err(err),
+    }
+
+    let review_id = sqlx::query_scalar!(
+        r#"
+        SELECT review_id
+        FROM review
+        WHERE nomer_id = ? AND store_id = ? AND item_id <=> ?
+        "#,
+        nomer_id,
+        store_id,
+        item_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        error!("Database error while fetching review to update: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch review")
+    })?;
+    // Deleted again in the meantime
+    let Some(review_id) = review_id else {
+        return Err((StatusCode::CONFLICT, "Review changed, please try again"));
+    };
+
+    let body = UpdateReviewRequest {
+        score: Some(score),
+        comment: Some(comment),
+    };
+    let review = update_review(db, nomer_id, i64::from(review_id), body).await?;
+
+    Ok((review, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO canteen (canteen_name, latitude, longitude, image_url) 
+               VALUES ('Test Canteen', 1.0, 103.0, 'test_canteen.jpg')"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        for i in 1..=2 {
+            sqlx::query!(
+                r#"INSERT INTO store (store_name, is_open, cuisine, information, canteen_id, image_url) 
+                   VALUES (?, TRUE, 'Test Cuisine', 'Test Info', 1, ?)"#,
+                format!("Test Store {}", i),
+                format!("test_store_{}.jpg", i)
+            )
+            .execute(db)
+            .await
+            .unwrap();
+        }
+
+        for i in 1..=2 {
+            sqlx::query!(
+                r#"INSERT INTO nomer (display_name, email, password_hash) 
+                   VALUES (?, ?, ?)"#,
+                format!("Test User {}", i),
+                format!("test{}@test.com", i),
+                format!("test_hash_{}", i)
+            )
+            .execute(db)
+            .await
+            .unwrap();
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_upsert_review(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let (created, is_new) = upsert_review(&db, 1, None, 1, 2, "Too salty".to_string())
+            .await
+            .unwrap();
+        assert!(is_new);
+        assert_eq!(created.updated_at, None);
+
+        // The second time round, the same review is edited
+        let (updated, is_new) = upsert_review(&db, 1, None, 1, 4, "Better now".to_string())
+            .await
+            .unwrap();
+        assert!(!is_new);
+        assert_eq!(updated.id, created.id);
+        assert_eq!(updated.score, 4);
+        assert_eq!(updated.comment, "Better now");
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.updated_at.is_some());
+
+        let revisions = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM review_revision WHERE review_id = ?",
+            created.id
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(revisions, 1);
+
+        // Other nomers and stores get reviews of their own
+        let (other_nomer, is_new) = upsert_review(&db, 1, None, 2, 5, "Great".to_string())
+            .await
+            .unwrap();
+        assert!(is_new);
+        assert_ne!(other_nomer.id, created.id);
+        let (other_store, is_new) = upsert_review(&db, 2, None, 1, 5, "Great".to_string())
+            .await
+            .unwrap();
+        assert!(is_new);
+        assert_ne!(other_store.id, created.id);
+    }
+
+    #[sqlx::test]
+    async fn test_upsert_review_invalid(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        assert_eq!(
+            upsert_review(&db, 1, None, 1, 6, "Too good".to_string())
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Score must be between 1 and 5")
+        );
+        assert_eq!(
+            upsert_review(&db, 999, None, 1, 5, "Nowhere".to_string())
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Invalid store or user ID")
+        );
+
+        // Editing is held to the same rules
+        upsert_review(&db, 1, None, 1, 3, "Fine".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            upsert_review(&db, 1, None, 1, 3, String::new())
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Comment cannot be empty")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_upsert_review_item(db: MySqlPool) {
+        setup_test_data(&db).await;
+
+        let (store_review, _) = upsert_review(&db, 1, None, 1, 4, "Good".to_string())
+            .await
+            .unwrap();
+        let (item_review, is_new) = upsert_review(&db, 1, Some(1), 1, 2, "Too sweet".to_string())
+            .await
+            .unwrap();
+        assert!(is_new);
+        assert_ne!(item_review.id, store_review.id);
+        assert_eq!(item_review.item_id, Some(1));
+
+        // Each item is edited apart from the store
+        let (updated, is_new) = upsert_review(&db, 1, Some(1), 1, 3, "Less sweet".to_string())
+            .await
+            .unwrap();
+        assert!(!is_new);
+        assert_eq!(updated.id, item_review.id);
+        let store_review =
+            crate::routes::review::read_one::fetch_review_by_id(&db, store_review.id)
+                .await
+                .unwrap();
+        assert_eq!(store_review.score, 4);
+    }
+}
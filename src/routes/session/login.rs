@@ -1,16 +1,43 @@
-use argon2::{Argon2, PasswordVerifier};
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::MySqlPool;
 use tracing::error;
 
-use crate::{models::Nomer, state::AppState};
+use crate::{
+    models::Nomer, passwords::Passwords, routes::user::create::normalize_email, state::AppState,
+};
 
 pub(super) async fn handle(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(body): Json<LoginRequest>,
 ) -> impl IntoResponse {
-    match login(&state, &body.email, &body.password).await {
+    let email = normalize_email(&body.email);
+
+    // Refuse outright while the account or client is locked out
+    let address = state.login_throttle().client_address(&headers, peer.ip());
+    match state.login_throttle().check(&email, address).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many login attempts",
+            )
+                .into_response();
+        }
+        Err(out) => return out.into_response(),
+    }
+
+    match login(&state, address, &email, &body.password).await {
         Ok((access_token, refresh_token)) => {
             let response = LoginResponse {
                 access_token,
@@ -38,43 +65,45 @@ struct LoginResponse {
 
 async fn login(
     state: &AppState,
+    address: IpAddr,
     email: &str,
     password: &str,
 ) -> Result<(String, String), (StatusCode, &'static str)> {
+    let throttle = state.login_throttle();
+    let passwords = state.passwords();
+
     // Fetch the nomer from the database using the provided email
-    let nomer = match get_nomer_by_email(state.db(), email).await {
-        Ok(Some(nomer)) => nomer,
-        Ok(None) => return Err((StatusCode::UNAUTHORIZED, "Invalid email or password")),
-        Err(e) => return Err(e),
-    };
+    let nomer = get_nomer_by_email(state.db(), email).await?;
 
-    // Verify password
-    match verify_password(password, &nomer.password_hash) {
-        Some(true) => (), // Password is correct
-        Some(false) => return Err((StatusCode::UNAUTHORIZED, "Invalid email or password")),
-        None => {
+    // Verify password, even if there is no such nomer, so that response
+    // times do not reveal which emails are registered
+    let verified = passwords
+        .verify(password, hash_to_verify(passwords, nomer.as_ref()))
+        .await;
+    let nomer = match (nomer, verified) {
+        (Some(nomer), Some(true)) => {
+            throttle.record_success(email).await?;
+            nomer
+        }
+        (None, _) | (_, Some(false)) => {
+            throttle.record_failure(email, address).await?;
+            return Err((StatusCode::UNAUTHORIZED, "Invalid email or password"));
+        }
+        (Some(_), None) => {
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Password verification failed",
             ));
         }
+    };
+
+    // Upgrade hashes made with outdated parameters while we have the password
+    if passwords.needs_rehash(&nomer.password_hash) {
+        rehash_password(state.db(), passwords, &nomer, password).await;
     }
 
     // Craft response with access and refresh tokens
-    let Some(access_token) = nomer.make_access_token(state.hmac()) else {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to create access token",
-        ));
-    };
-    let Some(refresh_token) = nomer.make_refresh_token(state.hmac()) else {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to create refresh token",
-        ));
-    };
-
-    Ok((access_token, refresh_token))
+    nomer.start_session(state.db(), state.tokens()).await
 }
 
 async fn get_nomer_by_email(
@@ -88,7 +117,13 @@ async fn get_nomer_by_email(
             nomer_id as id,
             display_name,
             email,
-            password_hash
+            password_hash,
+            token_generation,
+            email_verified_at,
+            pending_email,
+            created_at,
+            bio,
+            avatar_url
         FROM nomer WHERE email = ?
         "#,
         email
@@ -105,42 +140,125 @@ async fn get_nomer_by_email(
     }
 }
 
-fn verify_password(password: &str, hash: &str) -> Option<bool> {
-    let Ok(parsed_hash) = argon2::PasswordHash::new(hash) else {
-        // Invalid hash format
-        return None;
+/// The hash to verify a login attempt against: the nomer's own, or a dummy
+/// costing the same to check if there is no such nomer.
+fn hash_to_verify<'a>(passwords: &'a Passwords, nomer: Option<&'a Nomer>) -> &'a str {
+    nomer.map_or(passwords.dummy_hash(), |nomer| &nomer.password_hash)
+}
+
+/// Replace the password hash of `nomer` with one made with the current
+/// parameters. Failure only means trying again at the next login.
+async fn rehash_password(db: &MySqlPool, passwords: &Passwords, nomer: &Nomer, password: &str) {
+    let Some(phc) = passwords.hash(password).await else {
+        error!("Failed to rehash password of nomer {}", nomer.id);
+        return;
     };
-    Some(
-        Argon2::default()
-            .verify_password(password.as_bytes(), &parsed_hash)
-            .is_ok(),
+
+    // Leave the hash alone if the password was changed in the meantime
+    if let Err(e) = sqlx::query!(
+        "UPDATE nomer SET password_hash = ? WHERE nomer_id = ? AND password_hash = ?",
+        phc,
+        nomer.id,
+        nomer.password_hash
     )
+    .execute(db)
+    .await
+    {
+        error!("Failed to store rehashed password: {:?}", e);
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
     use argon2::{
-        PasswordHasher,
+        Argon2, PasswordHasher,
         password_hash::{SaltString, rand_core::OsRng},
     };
 
-    #[test]
-    fn test_verify_password() {
-        let password = "test_password";
-        let salt = SaltString::generate(OsRng);
-        let hash = Argon2::default()
-            .hash_password(password.as_bytes(), &salt)
+    use super::*;
+
+    fn test_nomer(password_hash: String) -> Nomer {
+        Nomer {
+            id: 1,
+            display_name: "Test".to_string(),
+            email: "test@test.com".to_string(),
+            password_hash,
+            token_generation: 0,
+            email_verified_at: None,
+            pending_email: None,
+            created_at: chrono::Utc::now(),
+            bio: None,
+            avatar_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hash_to_verify() {
+        let passwords = Passwords::for_testing();
+        let hash = passwords.hash("test_password").await.unwrap();
+        let nomer = test_nomer(hash.clone());
+
+        // Known emails verify against their own hash...
+        assert_eq!(hash_to_verify(&passwords, Some(&nomer)), hash);
+        assert_eq!(
+            passwords
+                .verify("test_password", hash_to_verify(&passwords, Some(&nomer)))
+                .await,
+            Some(true)
+        );
+
+        // ...and unknown ones against a dummy that is just as costly, so
+        // both paths actually run Argon2
+        let dummy = argon2::PasswordHash::new(hash_to_verify(&passwords, None)).unwrap();
+        let real = argon2::PasswordHash::new(&hash).unwrap();
+        assert_eq!(dummy.algorithm, real.algorithm);
+        assert_eq!(dummy.version, real.version);
+        assert_eq!(dummy.params, real.params);
+        assert_eq!(
+            passwords
+                .verify("test_password", hash_to_verify(&passwords, None))
+                .await,
+            Some(false)
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_rehash_password(db: MySqlPool) {
+        let passwords = Passwords::for_testing();
+
+        // Made with the library defaults rather than the configured costs
+        let salt = SaltString::generate(&mut OsRng);
+        let old_hash = Argon2::default()
+            .hash_password(b"test_password", &salt)
             .unwrap()
             .to_string();
+        assert!(passwords.needs_rehash(&old_hash));
 
-        assert_eq!(verify_password(password, &hash), Some(true));
+        sqlx::query!(
+            "INSERT INTO nomer (display_name, email, password_hash) VALUES (?, ?, ?)",
+            "Test",
+            "test@test.com",
+            old_hash
+        )
+        .execute(&db)
+        .await
+        .unwrap();
 
-        let wrong_password = "wrong_password";
-        assert_eq!(verify_password(wrong_password, &hash), Some(false));
+        let nomer = test_nomer(old_hash.clone());
+        rehash_password(&db, &passwords, &nomer, "test_password").await;
 
-        let invalid_hash = "invalid_hash_format";
-        assert_eq!(verify_password(password, invalid_hash), None);
+        let nomer = get_nomer_by_email(&db, "test@test.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(nomer.password_hash, old_hash);
+        assert!(!passwords.needs_rehash(&nomer.password_hash));
+        assert_eq!(
+            passwords
+                .verify("test_password", &nomer.password_hash)
+                .await,
+            Some(true)
+        );
     }
 
     #[sqlx::test]
@@ -0,0 +1,84 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+
+use super::refresh::verify_refresh_token;
+use crate::{models::RefreshToken, state::AppState, tokens::Tokens};
+
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    Json(body): Json<LogoutRequest>,
+) -> impl IntoResponse {
+    match logout(state.db(), state.tokens(), &body.refresh_token).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct LogoutRequest {
+    refresh_token: String,
+}
+
+async fn logout(
+    db: &MySqlPool,
+    tokens: &Tokens,
+    refresh_token: &str,
+) -> Result<(), (StatusCode, &'static str)> {
+    let claim = verify_refresh_token(tokens, refresh_token)?;
+
+    // Revoke the whole family, so the device's session ends for good
+    RefreshToken::revoke(db, &claim.jti).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_logout(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+        let mut claim = tokens.claim("1".to_string(), false);
+        claim.jti = token_id.clone();
+        let refresh_token = tokens.sign(&claim).unwrap();
+
+        assert!(logout(&db, &tokens, &refresh_token).await.is_ok());
+
+        let result = RefreshToken::rotate(&db, &token_id, 1).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token revoked")
+        );
+
+        // Logging out again is harmless
+        assert!(logout(&db, &tokens, &refresh_token).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_logout_invalid_token(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+
+        let result = logout(&db, &tokens, "not.a.token").await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token")
+        );
+    }
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,10 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+
+use crate::{models::Nomer, state::AppState};
+
+pub(super) async fn handle(State(state): State<AppState>, nomer: Nomer) -> impl IntoResponse {
+    match Nomer::revoke_all_sessions(state.db(), nomer.id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
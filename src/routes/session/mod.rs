@@ -1,15 +1,19 @@
 use axum::{
     Router,
-    routing::{post, put},
+    routing::{delete, post, put},
 };
 
 use crate::state::AppState;
 
-mod login;
+pub(super) mod login;
+mod logout;
+mod logout_all;
 mod refresh;
 
 pub(super) fn make_router() -> Router<AppState> {
     Router::new()
         .route("/", post(login::handle))
         .route("/", put(refresh::handle))
+        .route("/", delete(logout::handle))
+        .route("/all", delete(logout_all::handle))
 }
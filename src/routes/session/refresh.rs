@@ -1,18 +1,24 @@
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
-use hmac::Hmac;
-use jwt::{SignWithKey, VerifyWithKey};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
+use sqlx::MySqlPool;
 
-use crate::{models::NomerClaim, state::AppState};
+use crate::{
+    models::{ClaimSubject, Nomer, NomerClaim, RefreshToken},
+    state::AppState,
+    tokens::Tokens,
+};
 
 pub(super) async fn handle(
     State(state): State<AppState>,
     Json(body): Json<RefreshRequest>,
 ) -> impl IntoResponse {
-    match refresh(state.hmac(), &body.refresh_token) {
-        Ok(access_token) => {
-            let response = RefreshResponse { access_token };
+    match refresh(state.db(), state.tokens(), &body.refresh_token).await {
+        Ok((access_token, refresh_token)) => {
+            let response = RefreshResponse {
+                access_token,
+                refresh_token,
+            };
             (StatusCode::OK, Json(response)).into_response()
         }
         Err((status, message)) => (status, message).into_response(),
@@ -29,49 +35,174 @@ pub(super) struct RefreshRequest {
 #[serde(rename_all = "camelCase")]
 pub(super) struct RefreshResponse {
     access_token: String,
+    refresh_token: String,
 }
 
-fn refresh(hmac: &Hmac<Sha256>, refresh_token: &str) -> Result<String, (StatusCode, &'static str)> {
+async fn refresh(
+    db: &MySqlPool,
+    tokens: &Tokens,
+    refresh_token: &str,
+) -> Result<(String, String), (StatusCode, &'static str)> {
     // Validate the refresh token
-    let claim: NomerClaim = match refresh_token.verify_with_key(hmac) {
-        Ok(claims) => claims,
-        Err(_) => return Err((StatusCode::UNAUTHORIZED, "Invalid refresh token")),
-    };
+    let claim = verify_refresh_token(tokens, refresh_token)?;
 
-    // Check if the token is a refresh token
-    if claim.acc {
+    // Refresh tokens with a stored ID were only ever issued to nomer IDs
+    let ClaimSubject::Id(nomer_id) = claim.subject() else {
         return Err((StatusCode::UNAUTHORIZED, "Invalid refresh token"));
-    }
+    };
 
-    // Generate a new access token
-    let token = NomerClaim::make(claim.sub, 60 * 60, true);
+    // Consume the stored token, then issue its successor in the same family
+    let stored = RefreshToken::rotate(db, &claim.jti, nomer_id).await?;
+    let next_token_id = RefreshToken::issue(
+        db,
+        stored.nomer_id,
+        Some(&stored.family_id),
+        tokens.refresh_lifetime(),
+    )
+    .await?;
 
-    match token.sign_with_key(hmac) {
-        Ok(access_token) => Ok(access_token),
-        Err(_) => Err((
+    // Generate a new access token and the rotated refresh token
+    let Some(nomer) = Nomer::fetch_by_id(db, stored.nomer_id).await? else {
+        return Err((StatusCode::UNAUTHORIZED, "Nomer not found"));
+    };
+    let Some(access_token) = nomer.make_access_token(tokens) else {
+        return Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             "Failed to generate access token",
-        )),
+        ));
+    };
+    let Some(refresh_token) = nomer.make_refresh_token(tokens, next_token_id) else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to generate refresh token",
+        ));
+    };
+
+    Ok((access_token, refresh_token))
+}
+
+pub(super) fn verify_refresh_token(
+    tokens: &Tokens,
+    refresh_token: &str,
+) -> Result<NomerClaim, (StatusCode, &'static str)> {
+    let claim = tokens.verify(refresh_token)?;
+
+    // Check the token has not expired
+    claim.validate(Utc::now().timestamp(), tokens.leeway())?;
+
+    // Check if the token is a refresh token
+    if claim.acc {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid refresh token"));
     }
+
+    Ok(claim)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use hmac::Mac;
 
     #[test]
-    fn test_refresh() {
-        let hmac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
-        let claim = NomerClaim::make("test_user".to_string(), 60, false);
-        let refresh_token = claim.sign_with_key(&hmac).unwrap();
+    fn test_verify_refresh_token() {
+        let tokens = Tokens::for_testing();
+        let claim = tokens.claim("1".to_string(), false);
+        let refresh_token = tokens.sign(&claim).unwrap();
 
-        let result = refresh(&hmac, &refresh_token);
+        let result = verify_refresh_token(&tokens, &refresh_token);
         assert!(result.is_ok());
 
-        let access_token = result.unwrap();
-        let verified_claim: NomerClaim = access_token.verify_with_key(&hmac).unwrap();
-        assert_eq!(verified_claim.sub, "test_user");
-        assert!(verified_claim.acc);
+        let verified_claim = result.unwrap();
+        assert_eq!(verified_claim.sub, "1");
+        assert_eq!(verified_claim.jti, claim.jti);
+        assert!(!verified_claim.acc);
+    }
+
+    #[test]
+    fn test_verify_refresh_token_rejects_access_token() {
+        let tokens = Tokens::for_testing();
+        let access_token = tokens.sign(&tokens.claim("1".to_string(), true)).unwrap();
+
+        let result = verify_refresh_token(&tokens, &access_token);
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid refresh token")
+        );
+    }
+
+    #[test]
+    fn test_verify_refresh_token_rejects_garbage() {
+        let tokens = Tokens::for_testing();
+
+        let result = verify_refresh_token(&tokens, "not.a.token");
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token")
+        );
+    }
+
+    #[test]
+    fn test_verify_refresh_token_rejects_expired() {
+        let tokens = Tokens::for_testing();
+        let mut claim = tokens.claim("1".to_string(), false);
+        claim.iat -= 300;
+        claim.exp = claim.iat + 120;
+        let refresh_token = tokens.sign(&claim).unwrap();
+
+        let result = verify_refresh_token(&tokens, &refresh_token);
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Token expired")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_rotates_token(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        setup_test_data(&db).await;
+
+        let token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+        let mut claim = tokens.claim("1".to_string(), false);
+        claim.jti = token_id.clone();
+        let refresh_token = tokens.sign(&claim).unwrap();
+
+        let (access_token, rotated) = refresh(&db, &tokens, &refresh_token).await.unwrap();
+        let access_claim = tokens.verify(&access_token).unwrap();
+        assert!(access_claim.acc);
+        assert_eq!(access_claim.sub, "1");
+
+        let rotated_claim = tokens.verify(&rotated).unwrap();
+        assert!(!rotated_claim.acc);
+        assert_ne!(rotated_claim.jti, token_id);
+
+        // The old token is spent, and replaying it burns the new one too
+        let result = refresh(&db, &tokens, &refresh_token).await;
+        assert!(result.is_err());
+        let result = refresh(&db, &tokens, &rotated).await;
+        assert!(result.is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_unknown_token_id(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let refresh_token = tokens.sign(&tokens.claim("1".to_string(), false)).unwrap();
+
+        let result = refresh(&db, &tokens, &refresh_token).await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid refresh token")
+        );
+    }
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(db)
+        .await
+        .unwrap();
     }
 }
@@ -1,22 +1,34 @@
-use argon2::{
-    Argon2,
-    password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
 };
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use email_address::EmailAddress;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlx::MySqlPool;
 use tracing::error;
 
-use crate::state::AppState;
+use crate::{
+    mail::Mailer, models::EmailVerification, passwords::Passwords, state::AppState, tokens::Tokens,
+};
 
 pub(super) async fn handle(
     State(state): State<AppState>,
     Json(body): Json<CreateRequest>,
 ) -> impl IntoResponse {
-    match create_user(&body, state.db()).await {
+    match create_user(
+        &body,
+        state.db(),
+        state.tokens(),
+        state.passwords(),
+        state.mailer(),
+        state.email_domains(),
+    )
+    .await
+    {
         Ok(msg) => (StatusCode::CREATED, msg).into_response(),
-        Err(err) => err.into_response(),
+        Err(err) => err,
     }
 }
 
@@ -28,41 +40,132 @@ pub(super) struct CreateRequest {
     password: String,
 }
 
-fn validate_display_name(display_name: &str) -> bool {
+/// Body of the response to a sign-up request with invalid fields.
+#[derive(Debug, Serialize)]
+struct ValidationErrorResponse {
+    message: &'static str,
+    errors: Vec<FieldError>,
+}
+
+/// A field of a sign-up request that failed validation.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct FieldError {
+    /// Name of the field, as in the request body.
+    field: &'static str,
+    /// Machine-readable name of the rule the field broke.
+    code: &'static str,
+    message: &'static str,
+}
+
+impl FieldError {
+    fn new(field: &'static str, code: &'static str, message: &'static str) -> Self {
+        Self {
+            field,
+            code,
+            message,
+        }
+    }
+}
+
+pub(super) fn validate_display_name(display_name: &str) -> bool {
     display_name.is_empty() || display_name.len() > 50
 }
 
-fn validate_email(email: &str) -> bool {
+pub(super) fn validate_email(email: &str) -> bool {
     !EmailAddress::is_valid(email)
 }
 
-fn validate_password(password: &str) -> bool {
-    password.is_empty() || password.len() < 8 || password.len() > 100
+/// Check whether `email` is at one of `domains` or a subdomain thereof.
+/// Any domain is allowed if `domains` is empty.
+pub(super) fn is_allowed_domain(email: &str, domains: &[String]) -> bool {
+    let Some((_, domain)) = email.rsplit_once('@') else {
+        return false;
+    };
+
+    domains.is_empty()
+        || domains.iter().any(|allowed| {
+            domain == allowed
+                || domain
+                    .strip_suffix(allowed.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
 }
 
-fn hash_password(password: &str) -> Option<String> {
-    let salt = SaltString::generate(&mut OsRng);
-    let hasher = Argon2::default();
-    hasher
-        .hash_password(password.as_bytes(), &salt)
-        .map(|hash| hash.to_string())
-        .ok()
+/// Bring `email` to the form it is stored in, so that the same address
+/// cannot be registered twice with different case or whitespace.
+pub(crate) fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
 }
 
-async fn create_user(body: &CreateRequest, db: &MySqlPool) -> Result<String, impl IntoResponse> {
-    // Validate input
-    if validate_display_name(&body.display_name)
-        || validate_email(&body.email)
-        || validate_password(&body.password)
+/// Check every field of `body`, with `email` already normalized, and list
+/// what is wrong with each.
+async fn validate_request(
+    body: &CreateRequest,
+    email: &str,
+    passwords: &Passwords,
+    email_domains: &[String],
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    if body.display_name.is_empty() {
+        errors.push(FieldError::new(
+            "displayName",
+            "required",
+            "Display name is required",
+        ));
+    } else if validate_display_name(&body.display_name) {
+        errors.push(FieldError::new(
+            "displayName",
+            "too_long",
+            "Display name must be at most 50 characters long",
+        ));
+    }
+
+    if validate_email(email) {
+        errors.push(FieldError::new("email", "invalid", "Invalid email"));
+    } else if !is_allowed_domain(email, email_domains) {
+        errors.push(FieldError::new(
+            "email",
+            "domain_not_allowed",
+            "Email domain not allowed",
+        ));
+    }
+
+    if let Err(err) = passwords
+        .validate(&body.password, email, &body.display_name)
+        .await
     {
-        return Err((StatusCode::BAD_REQUEST, "Invalid input"));
+        errors.push(FieldError::new("password", err.code(), err.message()));
+    }
+
+    errors
+}
+
+async fn create_user(
+    body: &CreateRequest,
+    db: &MySqlPool,
+    tokens: &Tokens,
+    passwords: &Passwords,
+    mailer: &Mailer,
+    email_domains: &[String],
+) -> Result<String, Response> {
+    let email = normalize_email(&body.email);
+
+    // Validate input, telling the client about every field it has to fix
+    let errors = validate_request(body, &email, passwords, email_domains).await;
+    if !errors.is_empty() {
+        let response = ValidationErrorResponse {
+            message: "Invalid input",
+            errors,
+        };
+        return Err((StatusCode::BAD_REQUEST, Json(response)).into_response());
     }
 
     // Check display name and email uniqueness
     let display_name_exists = match sqlx::query!(
         "SELECT COUNT(*) AS count FROM nomer WHERE display_name = ? OR email = ?",
         body.display_name,
-        body.email
+        email
     )
     .fetch_one(db)
     .await
@@ -70,40 +173,55 @@ async fn create_user(body: &CreateRequest, db: &MySqlPool) -> Result<String, imp
         Ok(r) => r.count > 0,
         Err(e) => {
             error!("Database query failed: {e}");
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Database query failed"));
+            return Err(
+                (StatusCode::INTERNAL_SERVER_ERROR, "Database query failed").into_response()
+            );
         }
     };
     if display_name_exists {
-        return Err((StatusCode::CONFLICT, "Display name or email already exists"));
+        return Err((StatusCode::CONFLICT, "Display name or email already exists").into_response());
     }
 
     // Hash password
-    let Some(phc) = hash_password(&body.password) else {
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"));
+    let Some(phc) = passwords.hash(&body.password).await else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password").into_response());
     };
 
     // Insert user into database
-    match sqlx::query!(
+    let nomer_id = match sqlx::query!(
         "INSERT INTO nomer (display_name, email, password_hash) VALUES (?, ?, ?)",
         body.display_name,
-        body.email,
+        email,
         phc
     )
     .execute(db)
     .await
     {
-        Ok(_) => Ok("User created successfully".to_string()),
+        Ok(result) => result.last_insert_id(),
         Err(e) => {
             error!("Failed to insert user into database: {e}");
-            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user"))
+            return Err(
+                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user").into_response()
+            );
         }
+    };
+
+    // Send a verification link; the nomer can ask for another if this fails
+    let Ok(nomer_id) = i64::try_from(nomer_id) else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user").into_response());
+    };
+    let token = EmailVerification::issue(db, tokens, nomer_id, &email)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    if let Err(e) = mailer.send_verification(&email, &token).await {
+        error!("Failed to send verification email: {e:?}");
     }
+
+    Ok("User created successfully".to_string())
 }
 
 #[cfg(test)]
 mod tests {
-    use argon2::{PasswordHash, PasswordVerifier};
-
     use super::*;
 
     #[test]
@@ -124,48 +242,221 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_password() {
-        assert!(validate_password("short"));
-        assert!(validate_password(""));
-        assert!(!validate_password("validpassword"));
-        assert!(!validate_password(
-            "thisisaverylongpasswordthatshouldbevalid"
-        ));
+    fn test_is_allowed_domain() {
+        let domains = ["u.nus.edu".to_string(), "nus.edu.sg".to_string()];
+
+        assert!(is_allowed_domain("e0123456@u.nus.edu", &domains));
+        assert!(is_allowed_domain("prof@nus.edu.sg", &domains));
+        assert!(is_allowed_domain("prof@comp.nus.edu.sg", &domains));
+        assert!(!is_allowed_domain("hello@abc.com", &domains));
+        assert!(!is_allowed_domain("hello@notnus.edu.sg", &domains));
+        assert!(!is_allowed_domain("hello@nus.edu.sg.evil.com", &domains));
+
+        // No allowlist, no restriction
+        assert!(is_allowed_domain("hello@abc.com", &[]));
     }
 
     #[test]
-    fn test_hash_password() {
-        let password = "test_password";
-        let hashed = hash_password(password);
-        let hashed_2 = hash_password(password);
+    fn test_normalize_email() {
+        assert_eq!(normalize_email("  Foo@U.NUS.EDU "), "foo@u.nus.edu");
+        assert_eq!(normalize_email("foo@u.nus.edu"), "foo@u.nus.edu");
+    }
+
+    fn request(display_name: &str, email: &str, password: &str) -> CreateRequest {
+        CreateRequest {
+            display_name: display_name.to_string(),
+            email: email.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    /// The fields and codes of the errors found in `request`.
+    async fn field_errors(request: &CreateRequest) -> Vec<(&'static str, &'static str)> {
+        let domains = ["u.nus.edu".to_string()];
+        validate_request(
+            request,
+            &normalize_email(&request.email),
+            &Passwords::for_testing(),
+            &domains,
+        )
+        .await
+        .into_iter()
+        .map(|error| (error.field, error.code))
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_validate_request() {
+        let valid = request(
+            "Nommy",
+            "e0123456@u.nus.edu",
+            "correct horse battery staple",
+        );
+        assert_eq!(field_errors(&valid).await, []);
+    }
 
-        assert!(hashed.is_some());
-        assert!(hashed_2.is_some());
-        assert_ne!(hashed, hashed_2);
+    #[tokio::test]
+    async fn test_validate_request_display_name() {
+        let empty = request("", "e0123456@u.nus.edu", "correct horse battery staple");
+        assert_eq!(field_errors(&empty).await, [("displayName", "required")]);
 
-        let hmac = Argon2::default();
-        let hashed_str = hashed.unwrap();
-        let hashed = PasswordHash::new(&hashed_str);
+        let long = request(
+            &"n".repeat(51),
+            "e0123456@u.nus.edu",
+            "correct horse battery staple",
+        );
+        assert_eq!(field_errors(&long).await, [("displayName", "too_long")]);
+    }
 
-        assert!(hashed.is_ok());
+    #[tokio::test]
+    async fn test_validate_request_email() {
+        let invalid = request("Nommy", "not-an-email", "correct horse battery staple");
+        assert_eq!(field_errors(&invalid).await, [("email", "invalid")]);
 
-        let hashed = hashed.unwrap();
-        assert!(hmac.verify_password(password.as_bytes(), &hashed).is_ok());
+        let other_domain = request("Nommy", "nommy@gmail.com", "correct horse battery staple");
+        assert_eq!(
+            field_errors(&other_domain).await,
+            [("email", "domain_not_allowed")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_request_password() {
+        let cases = [
+            ("short", "length"),
+            ("E0123456 eats here daily", "contains_email"),
+            ("nommy eats here daily", "contains_display_name"),
+            ("breached_password", "breached"),
+            ("password123", "too_weak"),
+        ];
+        for (password, code) in cases {
+            let request = request("Nommy", "e0123456@u.nus.edu", password);
+            assert_eq!(
+                field_errors(&request).await,
+                [("password", code)],
+                "{password}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_request_every_field() {
+        let request = request("", "not-an-email", "short");
+        assert_eq!(
+            field_errors(&request).await,
+            [
+                ("displayName", "required"),
+                ("email", "invalid"),
+                ("password", "length")
+            ]
+        );
     }
 
     #[sqlx::test]
     async fn test_create_user(db: MySqlPool) {
         let request = CreateRequest {
             display_name: "test_user".to_string(),
-            password: "test_password".to_string(),
+            password: "correct horse battery staple".to_string(),
             email: "test@test.com".to_string(),
         };
-        let result = create_user(&request, &db).await;
+        let tokens = Tokens::for_testing();
+        let passwords = Passwords::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        let result = create_user(&request, &db, &tokens, &passwords, &mailer, &[]).await;
 
         assert!(result.is_ok());
 
-        let existed = create_user(&request, &db).await;
+        let existed = create_user(&request, &db, &tokens, &passwords, &mailer, &[]).await;
 
         assert!(existed.is_err());
+
+        // Only the first attempt sent a verification email
+        let mail = crate::mail::read_all(&mail_dir).unwrap();
+        assert_eq!(mail.len(), 1);
+        assert_eq!(mail[0].to, "test@test.com");
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_normalizes_email(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let passwords = Passwords::for_testing();
+        let (mailer, _) = Mailer::for_testing();
+        let domains = ["u.nus.edu".to_string()];
+
+        let request = CreateRequest {
+            display_name: "test_user".to_string(),
+            password: "correct horse battery staple".to_string(),
+            email: " Foo@U.NUS.EDU ".to_string(),
+        };
+        let result = create_user(&request, &db, &tokens, &passwords, &mailer, &domains).await;
+        assert!(result.is_ok());
+
+        let email = sqlx::query_scalar!("SELECT email FROM nomer WHERE display_name = 'test_user'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(email, "foo@u.nus.edu");
+
+        // The same address under another name is still taken
+        let request = CreateRequest {
+            display_name: "other_user".to_string(),
+            password: "correct horse battery staple".to_string(),
+            email: "foo@u.nus.edu".to_string(),
+        };
+        let result = create_user(&request, &db, &tokens, &passwords, &mailer, &domains).await;
+        assert!(result.is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_rejects_domain(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let passwords = Passwords::for_testing();
+        let (mailer, _) = Mailer::for_testing();
+        let domains = ["u.nus.edu".to_string()];
+
+        let request = CreateRequest {
+            display_name: "test_user".to_string(),
+            password: "correct horse battery staple".to_string(),
+            email: "test@gmail.com".to_string(),
+        };
+        let result = create_user(&request, &db, &tokens, &passwords, &mailer, &domains).await;
+        assert_eq!(
+            result.err().map(|e| e.into_response().status()),
+            Some(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_invalid_fields(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let passwords = Passwords::for_testing();
+        let (mailer, _) = Mailer::for_testing();
+
+        let request = request("test_user", "nommy@test.com", "test_user is hungry");
+        let result = create_user(&request, &db, &tokens, &passwords, &mailer, &[]).await;
+        let response = result.err().unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "message": "Invalid input",
+                "errors": [{
+                    "field": "password",
+                    "code": "contains_display_name",
+                    "message": "Password must not contain your display name",
+                }],
+            })
+        );
+
+        let count = sqlx::query_scalar!("SELECT COUNT(*) FROM nomer")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
     }
 }
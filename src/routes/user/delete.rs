@@ -0,0 +1,225 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use sqlx::{Error, MySqlPool};
+use tracing::error;
+
+use super::password_check::PasswordCheck;
+use crate::{models::Nomer, state::AppState};
+
+/// Handler for deleting the account of the authenticated nomer
+///
+/// Their reviews are deleted along with the account, unless they ask to
+/// keep them anonymously.
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    nomer: Nomer,
+    Json(body): Json<DeleteRequest>,
+) -> impl IntoResponse {
+    let check = PasswordCheck {
+        passwords: state.passwords(),
+        throttle: state.login_throttle(),
+        address: state.login_throttle().client_address(&headers, peer.ip()),
+    };
+
+    match delete_user(
+        state.db(),
+        &check,
+        &nomer,
+        &body.password,
+        body.anonymize_reviews,
+    )
+    .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct DeleteRequest {
+    password: String,
+    #[serde(default)]
+    anonymize_reviews: bool,
+}
+
+async fn delete_user(
+    db: &MySqlPool,
+    check: &PasswordCheck<'_>,
+    nomer: &Nomer,
+    password: &str,
+    anonymize_reviews: bool,
+) -> Result<(), (StatusCode, &'static str)> {
+    // A stolen access token alone must not be enough to delete an account
+    check.verify(nomer, password).await?;
+
+    let db_error = |e: Error| {
+        error!("Database error while deleting nomer: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    };
+    let mut tx = db.begin().await.map_err(db_error)?;
+
+    // Detach the reviews so that they survive the cascade, keeping only
+    // their current versions; earlier ones could still identify the nomer
+    if anonymize_reviews {
+        sqlx::query!(
+            r#"
+            DELETE review_revision
+            FROM review_revision
+            JOIN review ON review.review_id = review_revision.review_id
+            WHERE review.nomer_id = ?
+            "#,
+            nomer.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        sqlx::query!(
+            "UPDATE review SET nomer_id = NULL WHERE nomer_id = ?",
+            nomer.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+
+    // Sessions and outstanding tokens go with the nomer
+    sqlx::query!("DELETE FROM nomer WHERE nomer_id = ?", nomer.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::{passwords::Passwords, throttle::LoginThrottle};
+
+    async fn setup_test_data(db: &MySqlPool) -> Nomer {
+        let phc = Passwords::for_testing()
+            .hash("test_password")
+            .await
+            .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            phc
+        )
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, nomer_id, score, comment)
+               VALUES (1, 1, 5, 'Excellent'), (2, 1, 4, 'Good')"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO review_revision (review_id, score, comment, written_at)
+               VALUES (1, 4, 'Good enough', CURRENT_TIMESTAMP), (2, 3, 'Okay', CURRENT_TIMESTAMP)"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        Nomer::fetch_by_id(db, 1).await.unwrap().unwrap()
+    }
+
+    async fn review_authors(db: &MySqlPool) -> Vec<Option<i32>> {
+        sqlx::query_scalar!("SELECT nomer_id FROM review ORDER BY review_id")
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_delete_user(db: MySqlPool) {
+        let nomer = setup_test_data(&db).await;
+
+        assert_eq!(
+            delete_user(
+                &db,
+                &PasswordCheck {
+                    passwords: &Passwords::for_testing(),
+                    throttle: &LoginThrottle::for_testing(),
+                    address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                },
+                &nomer,
+                "test_password",
+                false
+            )
+            .await,
+            Ok(())
+        );
+        assert!(Nomer::fetch_by_id(&db, 1).await.unwrap().is_none());
+        assert!(review_authors(&db).await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_delete_user_anonymize_reviews(db: MySqlPool) {
+        let nomer = setup_test_data(&db).await;
+
+        assert_eq!(
+            delete_user(
+                &db,
+                &PasswordCheck {
+                    passwords: &Passwords::for_testing(),
+                    throttle: &LoginThrottle::for_testing(),
+                    address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                },
+                &nomer,
+                "test_password",
+                true
+            )
+            .await,
+            Ok(())
+        );
+        assert!(Nomer::fetch_by_id(&db, 1).await.unwrap().is_none());
+        assert_eq!(review_authors(&db).await, [None, None]);
+
+        let revisions = sqlx::query_scalar!("SELECT COUNT(*) FROM review_revision")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(revisions, 0);
+    }
+
+    #[sqlx::test]
+    async fn test_delete_user_wrong_password(db: MySqlPool) {
+        let nomer = setup_test_data(&db).await;
+
+        assert_eq!(
+            delete_user(
+                &db,
+                &PasswordCheck {
+                    passwords: &Passwords::for_testing(),
+                    throttle: &LoginThrottle::for_testing(),
+                    address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                },
+                &nomer,
+                "wrong_password",
+                false
+            )
+            .await,
+            Err((StatusCode::FORBIDDEN, "Incorrect password"))
+        );
+        assert!(Nomer::fetch_by_id(&db, 1).await.unwrap().is_some());
+        assert_eq!(review_authors(&db).await.len(), 2);
+    }
+}
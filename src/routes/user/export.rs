@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, header::CONTENT_DISPOSITION},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, MySqlPool};
+use tracing::error;
+
+use crate::{
+    models::{Nomer, Review},
+    state::AppState,
+};
+
+/// Handler for exporting everything stored about the authenticated nomer
+pub(super) async fn handle(State(state): State<AppState>, nomer: Nomer) -> impl IntoResponse {
+    match export_user(state.db(), nomer).await {
+        Ok(export) => (
+            StatusCode::OK,
+            [(
+                CONTENT_DISPOSITION,
+                "attachment; filename=\"nomnom-export.json\"",
+            )],
+            Json(export),
+        )
+            .into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportResponse {
+    exported_at: DateTime<Utc>,
+    profile: ExportProfile,
+    reviews: Vec<ExportReview>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportProfile {
+    id: i64,
+    display_name: String,
+    email: String,
+    pending_email: Option<String>,
+    email_verified_at: Option<DateTime<Utc>>,
+    joined_at: DateTime<Utc>,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportReview {
+    #[serde(flatten)]
+    review: Review,
+    /// Versions the review was edited from, oldest first.
+    revisions: Vec<ExportRevision>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRevision {
+    score: i64,
+    comment: String,
+    written_at: DateTime<Utc>,
+    replaced_at: DateTime<Utc>,
+}
+
+struct DbRevision {
+    review_id: i64,
+    score: i64,
+    comment: String,
+    written_at: DateTime<Utc>,
+    replaced_at: DateTime<Utc>,
+}
+
+async fn export_user(
+    db: &MySqlPool,
+    nomer: Nomer,
+) -> Result<ExportResponse, (StatusCode, &'static str)> {
+    let db_error = |e: Error| {
+        error!("Database error while exporting reviews: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    };
+
+    let reviews = sqlx::query_as!(
+        Review,
+        r#"
+        SELECT
+            review_id AS id,
+            nomer_id AS "nomer_id: i64",
+            store_id,
+            item_id AS "item_id: i64",
+            score,
+            comment,
+            created_at,
+            updated_at
+        FROM review
+        WHERE nomer_id = ?
+        ORDER BY created_at, review_id
+        "#,
+        nomer.id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(db_error)?;
+
+    let db_revisions = sqlx::query_as!(
+        DbRevision,
+        r#"
+        SELECT
+            review_revision.review_id,
+            review_revision.score,
+            review_revision.comment,
+            review_revision.written_at,
+            review_revision.replaced_at
+        FROM review_revision
+        JOIN review ON review.review_id = review_revision.review_id
+        WHERE review.nomer_id = ?
+        ORDER BY review_revision.written_at, review_revision.revision_id
+        "#,
+        nomer.id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(db_error)?;
+
+    let mut revisions = HashMap::<i64, Vec<ExportRevision>>::new();
+    for revision in db_revisions {
+        revisions
+            .entry(revision.review_id)
+            .or_default()
+            .push(ExportRevision {
+                score: revision.score,
+                comment: revision.comment,
+                written_at: revision.written_at,
+                replaced_at: revision.replaced_at,
+            });
+    }
+    let reviews = reviews
+        .into_iter()
+        .map(|review| ExportReview {
+            revisions: revisions.remove(&review.id).unwrap_or_default(),
+            review,
+        })
+        .collect();
+
+    // Credentials are left out; they are of no use to the nomer
+    Ok(ExportResponse {
+        exported_at: Utc::now(),
+        profile: ExportProfile {
+            id: nomer.id,
+            display_name: nomer.display_name,
+            email: nomer.email,
+            pending_email: nomer.pending_email,
+            email_verified_at: nomer.email_verified_at,
+            joined_at: nomer.created_at,
+            bio: nomer.bio,
+            avatar_url: nomer.avatar_url,
+        },
+        reviews,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_data(db: &MySqlPool) -> Nomer {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?), (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1",
+            "Test User 2",
+            "test2@test.com",
+            "test_hash_2"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, nomer_id, score, comment)
+               VALUES (1, 1, 5, 'Excellent'), (2, 2, 4, 'Good'), (3, 1, 3, 'Average')"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO review_revision (review_id, score, comment, written_at)
+               VALUES (1, 4, 'Good enough', CURRENT_TIMESTAMP), (2, 3, 'Okay', CURRENT_TIMESTAMP)"#
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        Nomer::fetch_by_id(db, 1).await.unwrap().unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_export_user(db: MySqlPool) {
+        let nomer = setup_test_data(&db).await;
+
+        let export = export_user(&db, nomer).await.unwrap();
+        assert_eq!(export.profile.id, 1);
+        assert_eq!(export.profile.email, "test1@test.com");
+
+        // Only their own reviews, oldest first
+        let comments: Vec<_> = export
+            .reviews
+            .iter()
+            .map(|r| r.review.comment.as_str())
+            .collect();
+        assert_eq!(comments, ["Excellent", "Average"]);
+
+        // Past versions are nested under the review they belong to
+        assert_eq!(export.reviews[0].revisions.len(), 1);
+        assert_eq!(export.reviews[0].revisions[0].comment, "Good enough");
+        assert!(export.reviews[1].revisions.is_empty());
+
+        let json = serde_json::to_value(&export).unwrap();
+        assert!(json["profile"].get("passwordHash").is_none());
+        assert_eq!(json["reviews"][0]["nomerId"], 1);
+        assert_eq!(json["reviews"][0]["revisions"][0]["score"], 4);
+    }
+}
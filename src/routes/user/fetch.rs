@@ -4,17 +4,30 @@ use serde::Serialize;
 use crate::{models::Nomer, state::AppState};
 
 pub(super) async fn handle(State(_): State<AppState>, nomer: Nomer) -> impl IntoResponse {
-    Json(FetchResponse {
-        id: nomer.id,
-        display_name: nomer.display_name,
-        email: nomer.email,
-    })
+    Json(FetchResponse::from(nomer))
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct FetchResponse {
+pub(super) struct FetchResponse {
     pub id: i64,
     pub display_name: String,
     pub email: String,
+    /// Address awaiting verification before it replaces `email`.
+    pub pending_email: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+impl From<Nomer> for FetchResponse {
+    fn from(nomer: Nomer) -> Self {
+        Self {
+            id: nomer.id,
+            display_name: nomer.display_name,
+            email: nomer.email,
+            pending_email: nomer.pending_email,
+            bio: nomer.bio,
+            avatar_url: nomer.avatar_url,
+        }
+    }
 }
@@ -4,6 +4,7 @@ use axum::{
     http::StatusCode,
     response::IntoResponse,
 };
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use sqlx::{Error, MySql, Pool};
 use tracing::error;
@@ -26,12 +27,19 @@ async fn fetch_user_public_info(
     db: &Pool<MySql>,
     user_id: i64,
 ) -> Result<FetchPublicResponse, (StatusCode, &'static str)> {
-    sqlx::query_as!(
-        FetchPublicResponse,
+    let db_error = |e: Error| {
+        error!("Database error while fetching user: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    };
+
+    let nomer = sqlx::query!(
         r#"
         SELECT
-            nomer_id as id,
-            display_name
+            nomer_id,
+            display_name,
+            bio,
+            avatar_url,
+            created_at
         FROM nomer
         WHERE nomer_id = ?
         "#,
@@ -43,9 +51,55 @@ async fn fetch_user_public_info(
         if let Error::RowNotFound = e {
             (StatusCode::NOT_FOUND, "User not found")
         } else {
-            error!("Database error while fetching user: {:?}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+            db_error(e)
         }
+    })?;
+
+    let stats = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS review_count,
+            CAST(AVG(score) AS DOUBLE) AS average_score
+        FROM review
+        WHERE nomer_id = ?
+        "#,
+        user_id
+    )
+    .fetch_one(db)
+    .await
+    .map_err(db_error)?;
+
+    // Ties go to the canteen listed first
+    let top_canteen = sqlx::query_as!(
+        TopCanteen,
+        r#"
+        SELECT
+            canteen.canteen_id AS id,
+            canteen.canteen_name AS name,
+            COUNT(*) AS review_count
+        FROM review
+        JOIN store ON store.store_id = review.store_id
+        JOIN canteen ON canteen.canteen_id = store.canteen_id
+        WHERE review.nomer_id = ?
+        GROUP BY canteen.canteen_id, canteen.canteen_name
+        ORDER BY review_count DESC, canteen.canteen_id
+        LIMIT 1
+        "#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(db_error)?;
+
+    Ok(FetchPublicResponse {
+        id: nomer.nomer_id.into(),
+        display_name: nomer.display_name,
+        bio: nomer.bio,
+        avatar_url: nomer.avatar_url,
+        joined_at: nomer.created_at,
+        review_count: stats.review_count,
+        average_score: stats.average_score,
+        top_canteen,
     })
 }
 
@@ -55,6 +109,22 @@ async fn fetch_user_public_info(
 pub struct FetchPublicResponse {
     pub id: i64,
     pub display_name: String,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub joined_at: DateTime<Utc>,
+    pub review_count: i64,
+    /// Mean score of the user's reviews, if they have any.
+    pub average_score: Option<f64>,
+    /// Canteen the user reviewed the most, if any.
+    pub top_canteen: Option<TopCanteen>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TopCanteen {
+    pub id: i64,
+    pub name: String,
+    pub review_count: i64,
 }
 
 #[cfg(test)]
@@ -203,6 +273,40 @@ mod tests {
         assert_eq!(response.display_name, "张三 🍜 Café");
     }
 
+    #[sqlx::test]
+    async fn test_fetch_user_public_info_stats(db: MySqlPool) {
+        setup_test_data(&db).await;
+        sqlx::query!("UPDATE nomer SET bio = 'I like noodles' WHERE nomer_id = 1")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        // Stores 1 and 2 are at canteen 1, store 7 at canteen 2
+        sqlx::query!(
+            r#"INSERT INTO review (store_id, nomer_id, score, comment)
+               VALUES (1, 1, 5, 'Excellent'), (2, 1, 4, 'Good'), (7, 1, 3, 'Average'),
+                      (7, 2, 1, 'Bad')"#
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let user = fetch_user_public_info(&db, 1).await.unwrap();
+        assert_eq!(user.bio.as_deref(), Some("I like noodles"));
+        assert_eq!(user.avatar_url, None);
+        assert_eq!(user.review_count, 3);
+        assert_eq!(user.average_score, Some(4.0));
+        let top_canteen = user.top_canteen.unwrap();
+        assert_eq!(top_canteen.id, 1);
+        assert_eq!(top_canteen.review_count, 2);
+
+        // No reviews, no stats
+        let user = fetch_user_public_info(&db, 3).await.unwrap();
+        assert_eq!(user.review_count, 0);
+        assert_eq!(user.average_score, None);
+        assert_eq!(user.top_canteen, None);
+    }
+
     async fn setup_test_data(db: &MySqlPool) {
         // Insert test users
         for i in 1..=3 {
@@ -1,10 +1,17 @@
-mod create;
+pub(super) mod create;
+mod delete;
+mod export;
 mod fetch;
 mod fetch_public;
+mod password;
+mod password_check;
+mod password_reset;
+mod update;
+mod verify;
 
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{delete, get, patch, post, put},
 };
 
 use crate::state::AppState;
@@ -13,5 +20,13 @@ pub(super) fn make_router() -> Router<AppState> {
     Router::new()
         .route("/", post(create::handle))
         .route("/", get(fetch::handle))
+        .route("/", patch(update::handle))
+        .route("/", delete(delete::handle))
+        .route("/export", get(export::handle))
         .route("/{id}", get(fetch_public::handle))
+        .route("/verify", post(verify::handle))
+        .route("/verify/resend", post(verify::resend))
+        .route("/password", put(password::handle))
+        .route("/password-reset", post(password_reset::handle))
+        .route("/password-reset/confirm", post(password_reset::confirm))
 }
@@ -0,0 +1,229 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use tracing::error;
+
+use super::password_check::PasswordCheck;
+use crate::{models::Nomer, state::AppState, tokens::Tokens};
+
+/// Handler for changing the password of the authenticated nomer
+///
+/// Every session is logged out, so the response carries fresh tokens for
+/// the session that made the change.
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    nomer: Nomer,
+    Json(body): Json<ChangePasswordRequest>,
+) -> impl IntoResponse {
+    let check = PasswordCheck {
+        passwords: state.passwords(),
+        throttle: state.login_throttle(),
+        address: state.login_throttle().client_address(&headers, peer.ip()),
+    };
+
+    match change_password(
+        state.db(),
+        state.tokens(),
+        &check,
+        &nomer,
+        &body.current_password,
+        &body.new_password,
+    )
+    .await
+    {
+        Ok((access_token, refresh_token)) => {
+            let response = ChangePasswordResponse {
+                access_token,
+                refresh_token,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangePasswordResponse {
+    access_token: String,
+    refresh_token: String,
+}
+
+async fn change_password(
+    db: &MySqlPool,
+    tokens: &Tokens,
+    check: &PasswordCheck<'_>,
+    nomer: &Nomer,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(String, String), (StatusCode, &'static str)> {
+    // A stolen access token alone must not be enough to take over
+    check.verify(nomer, current_password).await?;
+    let passwords = check.passwords;
+
+    passwords
+        .validate(new_password, &nomer.email, &nomer.display_name)
+        .await?;
+
+    // Hash with the current parameters, whatever the old hash used
+    let Some(phc) = passwords.hash(new_password).await else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"));
+    };
+
+    sqlx::query!(
+        "UPDATE nomer SET password_hash = ? WHERE nomer_id = ?",
+        phc,
+        nomer.id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| {
+        error!("Failed to update password: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    })?;
+
+    // Log out everywhere, then back in here with the new token generation
+    Nomer::revoke_all_sessions(db, nomer.id).await?;
+    let Some(nomer) = Nomer::fetch_by_id(db, nomer.id).await? else {
+        return Err((StatusCode::UNAUTHORIZED, "Nomer not found"));
+    };
+    nomer.start_session(db, tokens).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::{models::RefreshToken, passwords::Passwords, throttle::LoginThrottle};
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    async fn setup_test_data(db: &MySqlPool) -> Nomer {
+        let phc = Passwords::for_testing().hash("old_password").await.unwrap();
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            phc
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        Nomer::fetch_by_id(db, 1).await.unwrap().unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_change_password(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+        let other_session = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+
+        let (access_token, _) = change_password(
+            &db,
+            &tokens,
+            &check,
+            &nomer,
+            "old_password",
+            "correct horse battery staple",
+        )
+        .await
+        .unwrap();
+
+        let nomer = Nomer::fetch_by_id(&db, 1).await.unwrap().unwrap();
+        assert_eq!(
+            passwords
+                .verify("correct horse battery staple", &nomer.password_hash)
+                .await,
+            Some(true)
+        );
+
+        // Other sessions are logged out, but this one carries on
+        assert_eq!(
+            RefreshToken::rotate(&db, &other_session, 1)
+                .await
+                .unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token revoked")
+        );
+        let claim = tokens.verify(&access_token).unwrap();
+        assert_eq!(claim.generation, nomer.token_generation);
+    }
+
+    #[sqlx::test]
+    async fn test_change_password_wrong_current(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let result = change_password(
+            &db,
+            &tokens,
+            &check,
+            &nomer,
+            "wrong_password",
+            "correct horse battery staple",
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err(),
+            (StatusCode::FORBIDDEN, "Incorrect password")
+        );
+
+        let result = change_password(&db, &tokens, &check, &nomer, "old_password", "short").await;
+        assert_eq!(
+            result.unwrap_err(),
+            (
+                StatusCode::BAD_REQUEST,
+                "Password must be 8 to 100 characters long"
+            )
+        );
+
+        let result = change_password(
+            &db,
+            &tokens,
+            &check,
+            &nomer,
+            "old_password",
+            "test user 1 forever",
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err(),
+            (
+                StatusCode::BAD_REQUEST,
+                "Password must not contain your display name"
+            )
+        );
+    }
+}
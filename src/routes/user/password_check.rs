@@ -0,0 +1,92 @@
+use std::net::IpAddr;
+
+use axum::http::StatusCode;
+
+use crate::{models::Nomer, passwords::Passwords, throttle::LoginThrottle};
+
+/// Checks the current password a nomer gives to confirm a sensitive change.
+///
+/// Wrong passwords count as failed logins, so that an access token cannot be
+/// used to guess the password any faster than logging in can.
+pub(super) struct PasswordCheck<'a> {
+    pub(super) passwords: &'a Passwords,
+    pub(super) throttle: &'a LoginThrottle,
+    /// Address of the client making the change.
+    pub(super) address: IpAddr,
+}
+
+impl PasswordCheck<'_> {
+    pub(super) async fn verify(
+        &self,
+        nomer: &Nomer,
+        password: &str,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        if self
+            .throttle
+            .check(&nomer.email, self.address)
+            .await?
+            .is_some()
+        {
+            return Err((StatusCode::TOO_MANY_REQUESTS, "Too many password attempts"));
+        }
+
+        match self.passwords.verify(password, &nomer.password_hash).await {
+            Some(true) => self.throttle.record_success(&nomer.email).await,
+            Some(false) => {
+                self.throttle
+                    .record_failure(&nomer.email, self.address)
+                    .await?;
+                Err((StatusCode::FORBIDDEN, "Incorrect password"))
+            }
+            None => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Password verification failed",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify() {
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        let nomer = Nomer {
+            id: 1,
+            display_name: "Test".to_string(),
+            email: "test@test.com".to_string(),
+            password_hash: passwords.hash("test_password").await.unwrap(),
+            token_generation: 0,
+            email_verified_at: None,
+            pending_email: None,
+            created_at: chrono::Utc::now(),
+            bio: None,
+            avatar_url: None,
+        };
+
+        assert_eq!(check.verify(&nomer, "test_password").await, Ok(()));
+
+        // The account is locked out after a few wrong guesses, even for the
+        // right password
+        for _ in 0..3 {
+            assert_eq!(
+                check.verify(&nomer, "wrong_password").await,
+                Err((StatusCode::FORBIDDEN, "Incorrect password"))
+            );
+        }
+        assert_eq!(
+            check.verify(&nomer, "test_password").await,
+            Err((StatusCode::TOO_MANY_REQUESTS, "Too many password attempts"))
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use tracing::error;
+
+use super::create::normalize_email;
+use crate::{
+    mail::Mailer,
+    models::{Nomer, PasswordReset},
+    passwords::Passwords,
+    state::AppState,
+};
+
+/// Handler for requesting a password reset link
+///
+/// Always accepted, whether or not the email is registered, and the link is
+/// sent in the background so that timing does not tell either. Requests
+/// count against the client address as failed logins do, so that it cannot
+/// flood nomers with mail.
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<ResetRequest>,
+) -> impl IntoResponse {
+    let throttle = state.login_throttle();
+    let address = throttle.client_address(&headers, peer.ip());
+    match throttle.check_address(address).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many password reset requests",
+            )
+                .into_response();
+        }
+        Err(out) => return out.into_response(),
+    }
+    if let Err(out) = throttle.record_address(address).await {
+        return out.into_response();
+    }
+
+    let email = normalize_email(&body.email);
+    tokio::spawn(async move {
+        let result = request_reset(
+            state.db(),
+            state.mailer(),
+            &email,
+            state.password_reset_lifetime(),
+        )
+        .await;
+        if let Err((_, message)) = result {
+            error!("Failed to send password reset link: {message}");
+        }
+    });
+
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// Handler for choosing a new password with a reset token
+pub(super) async fn confirm(
+    State(state): State<AppState>,
+    Json(body): Json<ConfirmRequest>,
+) -> impl IntoResponse {
+    match reset_password(state.db(), state.passwords(), &body.token, &body.password).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+/// Seconds after sending a reset link before another is sent, unless the
+/// first is used up.
+const REISSUE_COOLDOWN: i64 = 15 * 60;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ResetRequest {
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ConfirmRequest {
+    token: String,
+    password: String,
+}
+
+async fn request_reset(
+    db: &MySqlPool,
+    mailer: &Mailer,
+    email: &str,
+    lifetime: i64,
+) -> Result<(), (StatusCode, &'static str)> {
+    // Nothing to do for unknown emails
+    let Some(nomer) = Nomer::fetch_by_email(db, email).await? else {
+        return Ok(());
+    };
+
+    // Only the hash of the last link is kept, so it cannot be sent again;
+    // rather than piling up links, the nomer is asked to use that one
+    if PasswordReset::recently_issued(db, nomer.id, REISSUE_COOLDOWN).await? {
+        return Ok(());
+    }
+
+    let token = PasswordReset::issue(db, nomer.id, lifetime).await?;
+    mailer
+        .send_password_reset(&nomer.email, &token)
+        .await
+        .map_err(|e| {
+            error!("Failed to send password reset email: {e:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to send email")
+        })
+}
+
+async fn reset_password(
+    db: &MySqlPool,
+    passwords: &Passwords,
+    token: &str,
+    password: &str,
+) -> Result<(), (StatusCode, &'static str)> {
+    // The password may not contain the email or display name of whoever it
+    // is for, so find out who that is before the token is used up
+    let nomer = match PasswordReset::nomer_id(db, token).await? {
+        Some(nomer_id) => Nomer::fetch_by_id(db, nomer_id).await?,
+        None => None,
+    };
+    let Some(nomer) = nomer else {
+        return Err((StatusCode::BAD_REQUEST, "Invalid or expired reset token"));
+    };
+    passwords
+        .validate(password, &nomer.email, &nomer.display_name)
+        .await?;
+
+    let Some(phc) = passwords.hash(password).await else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"));
+    };
+
+    // Whoever held the old password should not stay logged in
+    let nomer_id = PasswordReset::confirm(db, token, &phc).await?;
+    Nomer::revoke_all_sessions(db, nomer_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mail::read_all, models::RefreshToken};
+
+    async fn setup_test_data(db: &MySqlPool) {
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    /// The reset token from the last link mailed.
+    fn mailed_token(mail_dir: &std::path::Path) -> String {
+        let mail = read_all(mail_dir).unwrap();
+        let body = &mail.last().unwrap().body;
+        let (_, rest) = body.split_once("token=").unwrap();
+        rest.split_whitespace().next().unwrap().to_string()
+    }
+
+    #[sqlx::test]
+    async fn test_request_reset_unknown_email(db: MySqlPool) {
+        setup_test_data(&db).await;
+        let (mailer, mail_dir) = Mailer::for_testing();
+
+        assert!(
+            request_reset(&db, &mailer, "nobody@test.com", 60)
+                .await
+                .is_ok()
+        );
+        assert!(!mail_dir.exists());
+    }
+
+    #[sqlx::test]
+    async fn test_reset_password(db: MySqlPool) {
+        setup_test_data(&db).await;
+        let passwords = Passwords::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        let refresh_token_id = RefreshToken::issue(&db, 1, None, 60).await.unwrap();
+
+        request_reset(&db, &mailer, "test1@test.com", 60)
+            .await
+            .unwrap();
+        let token = mailed_token(&mail_dir);
+
+        // Asking again straight away does not send another link
+        request_reset(&db, &mailer, "test1@test.com", 60)
+            .await
+            .unwrap();
+        assert_eq!(read_all(&mail_dir).unwrap().len(), 1);
+
+        // Weak passwords are rejected without using up the token
+        assert_eq!(
+            reset_password(&db, &passwords, &token, "short").await,
+            Err((
+                StatusCode::BAD_REQUEST,
+                "Password must be 8 to 100 characters long"
+            ))
+        );
+        assert_eq!(
+            reset_password(&db, &passwords, &token, "test1@test.com!").await,
+            Err((
+                StatusCode::BAD_REQUEST,
+                "Password must not contain your email"
+            ))
+        );
+        assert_eq!(
+            reset_password(&db, &passwords, &token, "breached_password").await,
+            Err((
+                StatusCode::BAD_REQUEST,
+                "Password has appeared in a data breach"
+            ))
+        );
+
+        reset_password(&db, &passwords, &token, "correct horse battery staple")
+            .await
+            .unwrap();
+        let nomer = Nomer::fetch_by_id(&db, 1).await.unwrap().unwrap();
+        assert!(argon2::PasswordHash::new(&nomer.password_hash).is_ok());
+        assert_eq!(nomer.token_generation, 1);
+
+        // Existing sessions are gone, and the token is spent
+        assert_eq!(
+            RefreshToken::rotate(&db, &refresh_token_id, 1)
+                .await
+                .unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Refresh token revoked")
+        );
+        assert_eq!(
+            reset_password(&db, &passwords, &token, "battery horse staple correct").await,
+            Err((StatusCode::BAD_REQUEST, "Invalid or expired reset token"))
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use sqlx::{Error, MySqlPool};
+use tracing::error;
+
+use super::{
+    create::{is_allowed_domain, normalize_email, validate_display_name, validate_email},
+    fetch::FetchResponse,
+    password_check::PasswordCheck,
+};
+use crate::{
+    mail::Mailer,
+    models::{EmailVerification, Nomer},
+    state::AppState,
+    tokens::Tokens,
+};
+
+/// Handler for updating the profile of the authenticated nomer
+///
+/// A new display name, bio or avatar applies at once. A new email needs the
+/// current password, and is only recorded as pending and mailed a
+/// verification link; it replaces the current one when that link is used.
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    nomer: Nomer,
+    Json(body): Json<UpdateRequest>,
+) -> impl IntoResponse {
+    let check = PasswordCheck {
+        passwords: state.passwords(),
+        throttle: state.login_throttle(),
+        address: state.login_throttle().client_address(&headers, peer.ip()),
+    };
+
+    match update_user(
+        &body,
+        &nomer,
+        state.db(),
+        state.tokens(),
+        state.mailer(),
+        state.email_domains(),
+        &check,
+    )
+    .await
+    {
+        Ok(nomer) => (StatusCode::OK, Json(FetchResponse::from(nomer))).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct UpdateRequest {
+    display_name: Option<String>,
+    email: Option<String>,
+    /// Only needed to change the email.
+    current_password: Option<String>,
+    /// An empty bio or avatar URL clears it.
+    bio: Option<String>,
+    avatar_url: Option<String>,
+}
+
+fn validate_bio(bio: &str) -> bool {
+    bio.chars().count() > 280
+}
+
+fn validate_avatar_url(avatar_url: &str) -> bool {
+    !avatar_url.starts_with("https://")
+        || avatar_url.len() > 2048
+        || avatar_url.contains(char::is_whitespace)
+}
+
+// Taken by value so that it can be handed straight to `map_err`
+#[allow(clippy::needless_pass_by_value)]
+fn db_error(e: Error) -> (StatusCode, &'static str) {
+    error!("Database error while updating nomer: {:?}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+/// Check `display_name` is valid and not taken.
+async fn check_display_name(
+    db: &MySqlPool,
+    display_name: &str,
+) -> Result<(), (StatusCode, &'static str)> {
+    if validate_display_name(display_name) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid display name"));
+    }
+
+    let exists = sqlx::query!(
+        "SELECT COUNT(*) AS count FROM nomer WHERE display_name = ?",
+        display_name
+    )
+    .fetch_one(db)
+    .await
+    .map_err(db_error)?
+    .count
+        > 0;
+    if exists {
+        return Err((StatusCode::CONFLICT, "Display name already exists"));
+    }
+
+    Ok(())
+}
+
+/// Check `email` is valid, at an allowed domain and not taken.
+async fn check_email(
+    db: &MySqlPool,
+    email: &str,
+    email_domains: &[String],
+) -> Result<(), (StatusCode, &'static str)> {
+    if validate_email(email) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid email"));
+    }
+    if !is_allowed_domain(email, email_domains) {
+        return Err((StatusCode::BAD_REQUEST, "Email domain not allowed"));
+    }
+
+    let exists = sqlx::query!("SELECT COUNT(*) AS count FROM nomer WHERE email = ?", email)
+        .fetch_one(db)
+        .await
+        .map_err(db_error)?
+        .count
+        > 0;
+    if exists {
+        return Err((StatusCode::CONFLICT, "Email already exists"));
+    }
+
+    Ok(())
+}
+
+async fn update_user(
+    body: &UpdateRequest,
+    nomer: &Nomer,
+    db: &MySqlPool,
+    tokens: &Tokens,
+    mailer: &Mailer,
+    email_domains: &[String],
+    check: &PasswordCheck<'_>,
+) -> Result<Nomer, (StatusCode, &'static str)> {
+    // Validate everything before changing anything
+    let display_name = body
+        .display_name
+        .as_deref()
+        .filter(|name| *name != nomer.display_name);
+    if let Some(display_name) = display_name {
+        check_display_name(db, display_name).await?;
+    }
+
+    let bio = body
+        .bio
+        .as_deref()
+        .map(|bio| Some(bio).filter(|bio| !bio.is_empty()));
+    if bio.flatten().is_some_and(validate_bio) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid bio"));
+    }
+    let avatar_url = body
+        .avatar_url
+        .as_deref()
+        .map(|url| Some(url).filter(|url| !url.is_empty()));
+    if avatar_url.flatten().is_some_and(validate_avatar_url) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid avatar URL"));
+    }
+
+    // Asking for the current address again cancels a pending change
+    let email = body.email.as_deref().map(normalize_email);
+    let pending_email = email.as_deref().filter(|email| *email != nomer.email);
+    if let Some(email) = pending_email {
+        // Whoever controls the email controls the account, through password
+        // resets, so a stolen access token alone must not be enough
+        let Some(current_password) = body.current_password.as_deref() else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Current password required to change email",
+            ));
+        };
+        check.verify(nomer, current_password).await?;
+        check_email(db, email, email_domains).await?;
+    }
+
+    // All or nothing, so that a failure part way does not leave some of
+    // the changes made
+    let mut tx = db.begin().await.map_err(db_error)?;
+
+    if let Some(display_name) = display_name {
+        sqlx::query!(
+            "UPDATE nomer SET display_name = ? WHERE nomer_id = ?",
+            display_name,
+            nomer.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            // Taken since we checked
+            Error::Database(db_err) if db_err.is_unique_violation() => {
+                (StatusCode::CONFLICT, "Display name already exists")
+            }
+            e => db_error(e),
+        })?;
+    }
+
+    if let Some(bio) = bio {
+        sqlx::query!("UPDATE nomer SET bio = ? WHERE nomer_id = ?", bio, nomer.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    }
+
+    if let Some(avatar_url) = avatar_url {
+        sqlx::query!(
+            "UPDATE nomer SET avatar_url = ? WHERE nomer_id = ?",
+            avatar_url,
+            nomer.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+
+    if email.is_some() {
+        sqlx::query!(
+            "UPDATE nomer SET pending_email = ? WHERE nomer_id = ?",
+            pending_email,
+            nomer.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+
+    let token = match pending_email {
+        Some(email) => Some(EmailVerification::issue(&mut *tx, tokens, nomer.id, email).await?),
+        None => None,
+    };
+    tx.commit().await.map_err(db_error)?;
+
+    // The nomer can ask again if the link does not arrive
+    if let (Some(email), Some(token)) = (pending_email, token) {
+        if let Err(e) = mailer.send_email_change(email, &token).await {
+            error!("Failed to send email change verification: {e:?}");
+        }
+        if let Err(e) = mailer.send_email_change_notice(&nomer.email, email).await {
+            error!("Failed to send email change notice: {e:?}");
+        }
+    }
+
+    match Nomer::fetch_by_id(db, nomer.id).await? {
+        Some(nomer) => Ok(nomer),
+        None => Err((StatusCode::UNAUTHORIZED, "Nomer not found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::{passwords::Passwords, throttle::LoginThrottle};
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    async fn setup_test_data(db: &MySqlPool) -> Nomer {
+        let phc = Passwords::for_testing()
+            .hash("test_password")
+            .await
+            .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?), (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            phc,
+            "Test User 2",
+            "test2@test.com",
+            "test_hash_2"
+        )
+        .execute(db)
+        .await
+        .unwrap();
+
+        Nomer::fetch_by_id(db, 1).await.unwrap().unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_update_display_name(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, _) = Mailer::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let request = UpdateRequest {
+            display_name: Some("New Name".to_string()),
+            ..Default::default()
+        };
+        let nomer = update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+            .await
+            .unwrap();
+        assert_eq!(nomer.display_name, "New Name");
+        assert_eq!(nomer.email, "test1@test.com");
+
+        let request = UpdateRequest {
+            display_name: Some("Test User 2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+                .await
+                .unwrap_err(),
+            (StatusCode::CONFLICT, "Display name already exists")
+        );
+
+        let request = UpdateRequest {
+            display_name: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Invalid display name")
+        );
+    }
+
+    #[test]
+    fn test_validate_avatar_url() {
+        assert!(!validate_avatar_url("https://example.com/me.png"));
+        assert!(validate_avatar_url("http://example.com/me.png"));
+        assert!(validate_avatar_url("javascript:alert(1)"));
+        assert!(validate_avatar_url("https://example.com/my avatar.png"));
+    }
+
+    #[sqlx::test]
+    async fn test_update_profile(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, _) = Mailer::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let request = UpdateRequest {
+            bio: Some("I like noodles".to_string()),
+            avatar_url: Some("https://example.com/me.png".to_string()),
+            ..Default::default()
+        };
+        let nomer = update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+            .await
+            .unwrap();
+        assert_eq!(nomer.bio.as_deref(), Some("I like noodles"));
+        assert_eq!(
+            nomer.avatar_url.as_deref(),
+            Some("https://example.com/me.png")
+        );
+
+        // Empty values clear, missing ones are left alone
+        let request = UpdateRequest {
+            bio: Some(String::new()),
+            ..Default::default()
+        };
+        let nomer = update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+            .await
+            .unwrap();
+        assert_eq!(nomer.bio, None);
+        assert!(nomer.avatar_url.is_some());
+
+        let request = UpdateRequest {
+            bio: Some("a".repeat(281)),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+                .await
+                .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Invalid bio")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_update_email(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let request = UpdateRequest {
+            email: Some(" New@Test.com ".to_string()),
+            current_password: Some("test_password".to_string()),
+            ..Default::default()
+        };
+        let nomer = update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+            .await
+            .unwrap();
+
+        // Nothing changes until the new address is verified
+        assert_eq!(nomer.email, "test1@test.com");
+        assert_eq!(nomer.pending_email.as_deref(), Some("new@test.com"));
+
+        // The link goes to the new address, and a warning to the current one
+        let mail = crate::mail::read_all(&mail_dir).unwrap();
+        assert_eq!(mail.len(), 2);
+        let notice = mail
+            .iter()
+            .find(|mail| mail.to == "test1@test.com")
+            .unwrap();
+        assert!(notice.body.contains("new@test.com"));
+        let link = mail.iter().find(|mail| mail.to == "new@test.com").unwrap();
+        let (_, token) = link.body.split_once("?token=").unwrap();
+        let token = token.split_whitespace().next().unwrap();
+
+        assert_eq!(EmailVerification::confirm(&db, &tokens, token).await, Ok(1));
+        let nomer = Nomer::fetch_by_id(&db, 1).await.unwrap().unwrap();
+        assert_eq!(nomer.email, "new@test.com");
+        assert_eq!(nomer.pending_email, None);
+        assert!(nomer.is_verified());
+    }
+
+    #[sqlx::test]
+    async fn test_update_email_password(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let mut request = UpdateRequest {
+            email: Some("new@test.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+                .await
+                .unwrap_err(),
+            (
+                StatusCode::BAD_REQUEST,
+                "Current password required to change email"
+            )
+        );
+
+        request.current_password = Some("wrong_password".to_string());
+        assert_eq!(
+            update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+                .await
+                .unwrap_err(),
+            (StatusCode::FORBIDDEN, "Incorrect password")
+        );
+
+        let nomer = Nomer::fetch_by_id(&db, 1).await.unwrap().unwrap();
+        assert_eq!(nomer.pending_email, None);
+        assert!(!mail_dir.exists());
+    }
+
+    #[sqlx::test]
+    async fn test_update_email_conflict(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let request = UpdateRequest {
+            email: Some("test2@test.com".to_string()),
+            current_password: Some("test_password".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+                .await
+                .unwrap_err(),
+            (StatusCode::CONFLICT, "Email already exists")
+        );
+
+        let request = UpdateRequest {
+            email: Some("new@gmail.com".to_string()),
+            current_password: Some("test_password".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_user(
+                &request,
+                &nomer,
+                &db,
+                &tokens,
+                &mailer,
+                &["test.com".to_string()],
+                &check
+            )
+            .await
+            .unwrap_err(),
+            (StatusCode::BAD_REQUEST, "Email domain not allowed")
+        );
+
+        assert!(!mail_dir.exists());
+    }
+
+    #[sqlx::test]
+    async fn test_update_email_cancel(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, _) = Mailer::for_testing();
+        let passwords = Passwords::for_testing();
+        let throttle = LoginThrottle::for_testing();
+        let check = PasswordCheck {
+            passwords: &passwords,
+            throttle: &throttle,
+            address: ADDRESS,
+        };
+        let nomer = setup_test_data(&db).await;
+
+        let request = UpdateRequest {
+            email: Some("new@test.com".to_string()),
+            current_password: Some("test_password".to_string()),
+            ..Default::default()
+        };
+        let nomer = update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+            .await
+            .unwrap();
+        assert!(nomer.pending_email.is_some());
+
+        let request = UpdateRequest {
+            email: Some("test1@test.com".to_string()),
+            ..Default::default()
+        };
+        let nomer = update_user(&request, &nomer, &db, &tokens, &mailer, &[], &check)
+            .await
+            .unwrap();
+        assert_eq!(nomer.pending_email, None);
+    }
+}
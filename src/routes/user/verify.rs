@@ -0,0 +1,189 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use tracing::error;
+
+use crate::{
+    mail::Mailer,
+    models::{EmailVerification, Nomer},
+    state::AppState,
+    tokens::Tokens,
+};
+
+/// Handler for verifying an email address with the token mailed to it
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    Json(body): Json<VerifyRequest>,
+) -> impl IntoResponse {
+    match EmailVerification::confirm(state.db(), state.tokens(), &body.token).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+/// Handler for mailing the nomer a fresh verification link
+///
+/// The link goes to the address the nomer asked to change to, if any, or
+/// else to their current address until it is verified. Requests count against the client address as failed logins do, and
+/// each nomer is sent at most one link per cooldown, so that nobody can
+/// sign up with someone else's address and flood it with mail.
+pub(super) async fn resend(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    nomer: Nomer,
+) -> impl IntoResponse {
+    let Some(email) = unverified_email(&nomer) else {
+        return (StatusCode::CONFLICT, "Email already verified").into_response();
+    };
+
+    let throttle = state.login_throttle();
+    let address = throttle.client_address(&headers, peer.ip());
+    match throttle.check_address(address).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many verification emails",
+            )
+                .into_response();
+        }
+        Err(out) => return out.into_response(),
+    }
+    if let Err(out) = throttle.record_address(address).await {
+        return out.into_response();
+    }
+
+    match resend_verification(state.db(), state.tokens(), state.mailer(), &nomer, email).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+/// Seconds after sending a verification link before another is sent,
+/// unless the first is used up.
+const RESEND_COOLDOWN: i64 = 60;
+
+/// The address of `nomer` awaiting verification, if any.
+fn unverified_email(nomer: &Nomer) -> Option<&str> {
+    match &nomer.pending_email {
+        Some(pending_email) => Some(pending_email),
+        None => (!nomer.is_verified()).then_some(&nomer.email),
+    }
+}
+
+/// Mail `nomer` a fresh link to verify `email`, one of their addresses.
+async fn resend_verification(
+    db: &MySqlPool,
+    tokens: &Tokens,
+    mailer: &Mailer,
+    nomer: &Nomer,
+    email: &str,
+) -> Result<(), (StatusCode, &'static str)> {
+    if EmailVerification::recently_issued(db, nomer.id, email, RESEND_COOLDOWN).await? {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Verification email recently sent",
+        ));
+    }
+
+    let token = EmailVerification::issue(db, tokens, nomer.id, email).await?;
+    let sent = if email == nomer.email {
+        mailer.send_verification(email, &token).await
+    } else {
+        mailer.send_email_change(email, &token).await
+    };
+    sent.map_err(|e| {
+        error!("Failed to send verification email: {e:?}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to send verification email",
+        )
+    })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct VerifyRequest {
+    token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_resend_verification(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        let nomer = Nomer::fetch_by_id(&db, 1).await.unwrap().unwrap();
+
+        assert_eq!(unverified_email(&nomer), Some("test1@test.com"));
+        assert_eq!(
+            resend_verification(&db, &tokens, &mailer, &nomer, "test1@test.com").await,
+            Ok(())
+        );
+
+        // Asking again straight away sends nothing more
+        assert_eq!(
+            resend_verification(&db, &tokens, &mailer, &nomer, "test1@test.com").await,
+            Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                "Verification email recently sent"
+            ))
+        );
+        let mail = crate::mail::read_all(&mail_dir).unwrap();
+        assert_eq!(mail.len(), 1);
+        assert_eq!(mail[0].to, "test1@test.com");
+    }
+
+    #[sqlx::test]
+    async fn test_resend_email_change(db: MySqlPool) {
+        let tokens = Tokens::for_testing();
+        let (mailer, mail_dir) = Mailer::for_testing();
+        sqlx::query!(
+            r#"INSERT INTO nomer (display_name, email, password_hash)
+               VALUES (?, ?, ?)"#,
+            "Test User 1",
+            "test1@test.com",
+            "test_hash_1"
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        sqlx::query!("UPDATE nomer SET pending_email = 'new@test.com' WHERE nomer_id = 1")
+            .execute(&db)
+            .await
+            .unwrap();
+        let nomer = Nomer::fetch_by_id(&db, 1).await.unwrap().unwrap();
+
+        // A lost link to a new address can be sent again
+        assert_eq!(unverified_email(&nomer), Some("new@test.com"));
+        assert_eq!(
+            resend_verification(&db, &tokens, &mailer, &nomer, "new@test.com").await,
+            Ok(())
+        );
+        let mail = crate::mail::read_all(&mail_dir).unwrap();
+        assert_eq!(mail.len(), 1);
+        assert_eq!(mail[0].to, "new@test.com");
+        assert_eq!(mail[0].subject, "Confirm your new NomNom email address");
+    }
+}
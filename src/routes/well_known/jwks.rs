@@ -0,0 +1,17 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use serde::Serialize;
+
+use crate::{state::AppState, tokens::Jwk};
+
+pub(super) async fn handle(State(state): State<AppState>) -> impl IntoResponse {
+    Json(JwksResponse {
+        keys: state.tokens().jwks().to_vec(),
+    })
+}
+
+/// Our JWK set. Empty unless tokens are signed with an asymmetric key, as
+/// HMAC secrets must never be published.
+#[derive(Debug, Serialize)]
+pub(super) struct JwksResponse {
+    keys: Vec<Jwk>,
+}
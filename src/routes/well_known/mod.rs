@@ -0,0 +1,8 @@
+mod jwks;
+use axum::{Router, routing::get};
+
+use crate::state::AppState;
+
+pub(super) fn make_router() -> Router<AppState> {
+    Router::new().route("/jwks.json", get(jwks::handle))
+}
@@ -1,14 +1,24 @@
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
 use sqlx::{MySqlPool, mysql::MySqlPoolOptions};
 
-use crate::{config::Config, error_ctx};
+use crate::{
+    config::Config, error_ctx, mail::Mailer, passwords::Passwords, throttle::LoginThrottle,
+    tokens::Tokens,
+};
 
 #[derive(Clone)]
 pub(crate) struct AppState {
     db_pool: MySqlPool,
-    hmac: Hmac<Sha256>,
+    tokens: Tokens,
+    passwords: Passwords,
+    login_throttle: LoginThrottle,
+    mailer: Mailer,
+    email_domains: Arc<[String]>,
+    password_reset_lifetime: i64,
+    ranking_prior_weight: u32,
+    ranking_prior_score: Option<f64>,
 }
 
 impl AppState {
@@ -19,17 +29,79 @@ impl AppState {
             .await
             .with_context(error_ctx!("Failed to connect to database"))?;
 
-        // Initialise HMAC with the provided secret
-        let hmac = Hmac::<Sha256>::new_from_slice(config.hmac_secret.as_bytes())
-            .with_context(error_ctx!("Failed to create HMAC instance"))?;
-        Ok(Self { db_pool, hmac })
+        // Initialise token signing and verification
+        let tokens =
+            Tokens::from_config(config).with_context(error_ctx!("Failed to initialise tokens"))?;
+
+        // Initialise password hashing
+        let passwords = Passwords::from_config(config)
+            .with_context(error_ctx!("Failed to initialise password hashing"))?;
+
+        // Initialise failed login tracking
+        let login_throttle = LoginThrottle::from_config(config, &db_pool);
+
+        // Initialise outgoing mail
+        let mailer = Mailer::from_config(config);
+
+        // Parse the domains nomers may sign up with
+        let email_domains = config
+            .allowed_email_domains
+            .split(',')
+            .map(|domain| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+
+        Ok(Self {
+            db_pool,
+            tokens,
+            passwords,
+            login_throttle,
+            mailer,
+            email_domains,
+            password_reset_lifetime: config.password_reset_lifetime,
+            ranking_prior_weight: config.ranking_prior_weight,
+            ranking_prior_score: config.ranking_prior_score,
+        })
     }
 
     pub fn db(&self) -> &MySqlPool {
         &self.db_pool
     }
 
-    pub fn hmac(&self) -> &Hmac<Sha256> {
-        &self.hmac
+    pub fn tokens(&self) -> &Tokens {
+        &self.tokens
+    }
+
+    pub fn passwords(&self) -> &Passwords {
+        &self.passwords
+    }
+
+    pub fn login_throttle(&self) -> &LoginThrottle {
+        &self.login_throttle
+    }
+
+    pub fn mailer(&self) -> &Mailer {
+        &self.mailer
+    }
+
+    /// Email domains nomers may sign up with; empty if any is allowed.
+    pub fn email_domains(&self) -> &[String] {
+        &self.email_domains
+    }
+
+    /// Lifetime of a password reset link, in seconds.
+    pub fn password_reset_lifetime(&self) -> i64 {
+        self.password_reset_lifetime
+    }
+
+    /// Number of imaginary reviews stores start out with when ranked.
+    pub fn ranking_prior_weight(&self) -> u32 {
+        self.ranking_prior_weight
+    }
+
+    /// Score of the imaginary reviews stores start out with when ranked, if
+    /// not the average across all stores.
+    pub fn ranking_prior_score(&self) -> Option<f64> {
+        self.ranking_prior_score
     }
 }
@@ -0,0 +1,137 @@
+use axum::http::StatusCode;
+use chrono::DateTime;
+use sqlx::MySqlPool;
+use tracing::error;
+
+use super::{Attempts, Policy};
+
+/// Failed logins kept in the `login_attempt` table, shared by every instance.
+#[derive(Clone)]
+pub(super) struct DatabaseStore {
+    db: MySqlPool,
+}
+
+impl DatabaseStore {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Attempts>, (StatusCode, &'static str)> {
+        let row = sqlx::query!(
+            r#"
+            SELECT failures, last_failure_at, locked_until
+            FROM login_attempt
+            WHERE throttle_key = ?
+            "#,
+            key
+        )
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch login attempts: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        })?;
+
+        Ok(row.map(|row| Attempts {
+            failures: u32::try_from(row.failures).unwrap_or_default(),
+            last_failure: row.last_failure_at.timestamp(),
+            locked_until: row.locked_until.map(|t| t.timestamp()),
+        }))
+    }
+
+    pub async fn record_failure(
+        &self,
+        key: &str,
+        policy: &Policy,
+        now: i64,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        let map_err = |e: sqlx::Error| {
+            error!("Failed to record login attempt: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        };
+
+        // Lock the row so concurrent failures are all counted
+        let mut tx = self.db.begin().await.map_err(map_err)?;
+        let previous = sqlx::query!(
+            r#"
+            SELECT failures, last_failure_at, locked_until
+            FROM login_attempt
+            WHERE throttle_key = ?
+            FOR UPDATE
+            "#,
+            key
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?
+        .map(|row| Attempts {
+            failures: u32::try_from(row.failures).unwrap_or_default(),
+            last_failure: row.last_failure_at.timestamp(),
+            locked_until: row.locked_until.map(|t| t.timestamp()),
+        });
+
+        let next = policy.record_failure(previous, now);
+        let timestamp = |t: i64| DateTime::from_timestamp(t, 0).map(|t| t.naive_utc());
+        sqlx::query!(
+            r#"
+            REPLACE INTO login_attempt (throttle_key, failures, last_failure_at, locked_until)
+            VALUES (?, ?, ?, ?)
+            "#,
+            key,
+            next.failures,
+            timestamp(next.last_failure),
+            next.locked_until.and_then(timestamp)
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+
+        tx.commit().await.map_err(map_err)
+    }
+
+    pub async fn remove(&self, key: &str) -> Result<(), (StatusCode, &'static str)> {
+        sqlx::query!("DELETE FROM login_attempt WHERE throttle_key = ?", key)
+            .execute(&self.db)
+            .await
+            .map_err(|e| {
+                error!("Failed to clear login attempts: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+            })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_record_failure(db: MySqlPool) {
+        let store = DatabaseStore::new(db);
+        let policy = Policy {
+            max_failures: 2,
+            lockout: 30,
+            max_lockout: 900,
+            window: 3600,
+        };
+
+        assert_eq!(store.get("account:a").await.unwrap(), None);
+
+        store
+            .record_failure("account:a", &policy, 1000)
+            .await
+            .unwrap();
+        store
+            .record_failure("account:a", &policy, 1001)
+            .await
+            .unwrap();
+        let attempts = store.get("account:a").await.unwrap().unwrap();
+        assert_eq!(attempts.failures, 2);
+        assert_eq!(attempts.last_failure, 1001);
+        assert_eq!(attempts.locked_until, Some(1031));
+
+        store.remove("account:a").await.unwrap();
+        assert_eq!(store.get("account:a").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{Attempts, Policy};
+
+/// Keys tracked before stale entries are swept out.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Failed logins kept in process memory. Each instance counts on its own,
+/// so this is only suitable for a single instance, and for tests.
+#[derive(Clone, Default)]
+pub(super) struct MemoryStore {
+    attempts: Arc<Mutex<HashMap<String, Attempts>>>,
+}
+
+impl MemoryStore {
+    pub fn get(&self, key: &str) -> Option<Attempts> {
+        self.lock().get(key).copied()
+    }
+
+    pub fn record_failure(&self, key: &str, policy: &Policy, now: i64) {
+        let mut attempts = self.lock();
+
+        if attempts.len() >= SWEEP_THRESHOLD {
+            attempts.retain(|_, a| {
+                now - a.last_failure < policy.window || a.retry_after(now).is_some()
+            });
+        }
+
+        let next = policy.record_failure(attempts.get(key).copied(), now);
+        attempts.insert(key.to_string(), next);
+    }
+
+    pub fn remove(&self, key: &str) {
+        self.lock().remove(key);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Attempts>> {
+        // The map is always left consistent, so a poisoned lock is still usable
+        self.attempts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,421 @@
+mod database;
+mod memory;
+
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, StatusCode};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::MySqlPool;
+
+use crate::config::{Config, ThrottleBackend};
+use database::DatabaseStore;
+use memory::MemoryStore;
+
+/// Counts failed logins per account and per client address, locking either
+/// out for a while once too many pile up.
+#[derive(Clone)]
+pub(crate) struct LoginThrottle {
+    store: Store,
+    account: Policy,
+    address: Policy,
+    trust_x_forwarded_for: bool,
+}
+
+#[derive(Clone)]
+enum Store {
+    Memory(MemoryStore),
+    Database(DatabaseStore),
+}
+
+/// When a run of failures turns into a lockout, and for how long.
+#[derive(Clone, Copy, Debug)]
+struct Policy {
+    max_failures: u32,
+    lockout: i64,
+    max_lockout: i64,
+    window: i64,
+}
+
+/// The failed logins recorded against one throttle key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Attempts {
+    failures: u32,
+    last_failure: i64,
+    locked_until: Option<i64>,
+}
+
+impl Policy {
+    /// The attempts after another failure at `now`.
+    ///
+    /// Every failure from `max_failures` on locks the key out, starting at
+    /// `lockout` and doubling each time up to `max_lockout`. A failure more
+    /// than `window` after the previous one starts a fresh count.
+    fn record_failure(&self, previous: Option<Attempts>, now: i64) -> Attempts {
+        let failures = match previous {
+            Some(previous) if now - previous.last_failure < self.window => previous.failures + 1,
+            _ => 1,
+        };
+
+        let locked_until = failures.checked_sub(self.max_failures).map(|excess| {
+            let lockout = self
+                .lockout
+                .saturating_mul(1 << excess.min(20))
+                .min(self.max_lockout);
+            now + lockout
+        });
+
+        Attempts {
+            failures,
+            last_failure: now,
+            locked_until,
+        }
+    }
+}
+
+impl Attempts {
+    /// Seconds until the lockout ends, if the key is locked out at `now`.
+    fn retry_after(&self, now: i64) -> Option<i64> {
+        self.locked_until
+            .filter(|&locked_until| locked_until > now)
+            .map(|locked_until| locked_until - now)
+    }
+}
+
+impl LoginThrottle {
+    pub fn from_config(config: &Config, db: &MySqlPool) -> Self {
+        let store = match config.login_throttle_backend {
+            ThrottleBackend::Memory => Store::Memory(MemoryStore::default()),
+            ThrottleBackend::Database => Store::Database(DatabaseStore::new(db.clone())),
+        };
+        let policy = |max_failures| Policy {
+            max_failures,
+            lockout: config.login_lockout,
+            max_lockout: config.login_max_lockout,
+            window: config.login_failure_window,
+        };
+
+        Self {
+            store,
+            account: policy(config.login_max_failures_per_account),
+            address: policy(config.login_max_failures_per_address),
+            trust_x_forwarded_for: config.trust_x_forwarded_for,
+        }
+    }
+
+    /// The address of the client, given the address of the peer that
+    /// connected to us.
+    pub fn client_address(&self, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+        if !self.trust_x_forwarded_for {
+            return peer;
+        }
+
+        // The proxy appends the address it saw, so the last entry is the
+        // only one the client cannot forge
+        headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .next_back()
+            .and_then(|address| address.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+
+    /// Check whether `email` may attempt to log in from `address`.
+    ///
+    /// Returns the number of seconds to wait if either is locked out.
+    pub async fn check(
+        &self,
+        email: &str,
+        address: IpAddr,
+    ) -> Result<Option<i64>, (StatusCode, &'static str)> {
+        self.check_at(email, address, Utc::now().timestamp()).await
+    }
+
+    /// Count a failed login for `email` from `address`.
+    pub async fn record_failure(
+        &self,
+        email: &str,
+        address: IpAddr,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        self.record_failure_at(email, address, Utc::now().timestamp())
+            .await
+    }
+
+    /// Forget the failed logins for `email` after a successful one.
+    ///
+    /// Failures from the client address are kept, so that an attacker cannot
+    /// reset them by logging in to an account of their own.
+    pub async fn record_success(&self, email: &str) -> Result<(), (StatusCode, &'static str)> {
+        match &self.store {
+            Store::Memory(store) => {
+                store.remove(&account_key(email));
+                Ok(())
+            }
+            Store::Database(store) => store.remove(&account_key(email)).await,
+        }
+    }
+
+    /// Check whether `address` is locked out, whichever account it is after.
+    pub async fn check_address(
+        &self,
+        address: IpAddr,
+    ) -> Result<Option<i64>, (StatusCode, &'static str)> {
+        self.retry_after(&[address_key(address)], Utc::now().timestamp())
+            .await
+    }
+
+    /// Count a request from `address` against the address alone.
+    ///
+    /// For requests that must be limited per client but cannot fail like a
+    /// login, such as asking for a password reset. The account is left out
+    /// so that nobody can lock a nomer out by making requests for them.
+    pub async fn record_address(&self, address: IpAddr) -> Result<(), (StatusCode, &'static str)> {
+        self.record(
+            [(address_key(address), &self.address)],
+            Utc::now().timestamp(),
+        )
+        .await
+    }
+
+    async fn check_at(
+        &self,
+        email: &str,
+        address: IpAddr,
+        now: i64,
+    ) -> Result<Option<i64>, (StatusCode, &'static str)> {
+        self.retry_after(&[account_key(email), address_key(address)], now)
+            .await
+    }
+
+    async fn record_failure_at(
+        &self,
+        email: &str,
+        address: IpAddr,
+        now: i64,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        self.record(
+            [
+                (account_key(email), &self.account),
+                (address_key(address), &self.address),
+            ],
+            now,
+        )
+        .await
+    }
+
+    /// Seconds to wait until none of `keys` is locked out any more.
+    async fn retry_after(
+        &self,
+        keys: &[String],
+        now: i64,
+    ) -> Result<Option<i64>, (StatusCode, &'static str)> {
+        let mut retry_after = None;
+        for key in keys {
+            let attempts = match &self.store {
+                Store::Memory(store) => store.get(key),
+                Store::Database(store) => store.get(key).await?,
+            };
+            let wait = attempts.and_then(|attempts| attempts.retry_after(now));
+            retry_after = retry_after.max(wait);
+        }
+
+        Ok(retry_after)
+    }
+
+    async fn record<const N: usize>(
+        &self,
+        keys: [(String, &Policy); N],
+        now: i64,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        for (key, policy) in keys {
+            match &self.store {
+                Store::Memory(store) => store.record_failure(&key, policy, now),
+                Store::Database(store) => store.record_failure(&key, policy, now).await?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn account_key(email: &str) -> String {
+    // Hashed, as emails are not length-checked before logging in, and the
+    // key must fit the `login_attempt` table
+    let email = email.trim().to_lowercase();
+    format!("account:{:x}", Sha256::digest(email.as_bytes()))
+}
+
+fn address_key(address: IpAddr) -> String {
+    // Treat IPv4 clients the same whether or not they arrive mapped to IPv6
+    format!("address:{}", address.to_canonical())
+}
+
+#[cfg(test)]
+impl LoginThrottle {
+    pub fn for_testing() -> Self {
+        let policy = |max_failures| Policy {
+            max_failures,
+            lockout: 30,
+            max_lockout: 900,
+            window: 3600,
+        };
+
+        Self {
+            store: Store::Memory(MemoryStore::default()),
+            account: policy(3),
+            address: policy(10),
+            trust_x_forwarded_for: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    #[test]
+    fn test_policy_lockout_doubles() {
+        let policy = LoginThrottle::for_testing().account;
+
+        let mut attempts = None;
+        let mut lockouts = Vec::new();
+        for _ in 0..8 {
+            let next = policy.record_failure(attempts, 1000);
+            lockouts.push(next.retry_after(1000));
+            attempts = Some(next);
+        }
+
+        assert_eq!(
+            lockouts,
+            vec![
+                None,
+                None,
+                Some(30),
+                Some(60),
+                Some(120),
+                Some(240),
+                Some(480),
+                Some(900)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_policy_window() {
+        let policy = LoginThrottle::for_testing().account;
+
+        let attempts = policy.record_failure(None, 0);
+        let attempts = policy.record_failure(Some(attempts), 3599);
+        assert_eq!(attempts.failures, 2);
+
+        // Long after the last failure, the count starts over
+        let attempts = policy.record_failure(Some(attempts), 3599 + 3600);
+        assert_eq!(attempts.failures, 1);
+        assert_eq!(attempts.locked_until, None);
+    }
+
+    #[tokio::test]
+    async fn test_account_lockout() {
+        let throttle = LoginThrottle::for_testing();
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        for now in 0..3 {
+            assert_eq!(
+                throttle.check_at("a@test.com", ADDRESS, now).await,
+                Ok(None)
+            );
+            throttle
+                .record_failure_at("a@test.com", ADDRESS, now)
+                .await
+                .unwrap();
+        }
+
+        // Locked out from anywhere, regardless of case
+        assert_eq!(
+            throttle.check_at("A@Test.com", other, 2).await,
+            Ok(Some(30))
+        );
+        assert_eq!(throttle.check_at("a@test.com", ADDRESS, 32).await, Ok(None));
+
+        // Other accounts are unaffected, and success resets the count
+        assert_eq!(throttle.check_at("b@test.com", ADDRESS, 2).await, Ok(None));
+        throttle.record_success("a@test.com").await.unwrap();
+        assert_eq!(throttle.check_at("a@test.com", ADDRESS, 2).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_address_lockout() {
+        let throttle = LoginThrottle::for_testing();
+
+        // Spraying one password across many accounts
+        for i in 0..10 {
+            let email = format!("user{i}@test.com");
+            throttle
+                .record_failure_at(&email, ADDRESS, 0)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            throttle.check_at("new@test.com", ADDRESS, 0).await,
+            Ok(Some(30))
+        );
+
+        // Success on some account does not lift the address lockout
+        throttle.record_success("user0@test.com").await.unwrap();
+        assert_eq!(
+            throttle.check_at("user0@test.com", ADDRESS, 0).await,
+            Ok(Some(30))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_address_requests() {
+        let throttle = LoginThrottle::for_testing();
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        for _ in 0..10 {
+            assert_eq!(throttle.check_address(ADDRESS).await, Ok(None));
+            throttle.record_address(ADDRESS).await.unwrap();
+        }
+        assert!(throttle.check_address(ADDRESS).await.unwrap().is_some());
+
+        // Accounts are not held responsible
+        let now = Utc::now().timestamp();
+        assert_eq!(throttle.check_at("a@test.com", other, now).await, Ok(None));
+    }
+
+    #[test]
+    fn test_account_key() {
+        assert_eq!(account_key(" A@Test.com"), account_key("a@test.com"));
+        assert_ne!(account_key("a@test.com"), account_key("b@test.com"));
+
+        let long_email = format!("{}@test.com", "a".repeat(1000));
+        assert_eq!(account_key(&long_email).len(), "account:".len() + 64);
+    }
+
+    #[test]
+    fn test_client_address() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-forwarded-for", "1.2.3.4, 5.6.7.8".parse().unwrap());
+
+        let throttle = LoginThrottle::for_testing();
+        assert_eq!(throttle.client_address(&headers, ADDRESS), ADDRESS);
+
+        let throttle = LoginThrottle {
+            trust_x_forwarded_for: true,
+            ..LoginThrottle::for_testing()
+        };
+        assert_eq!(
+            throttle.client_address(&headers, ADDRESS),
+            IpAddr::V4(Ipv4Addr::new(5, 6, 7, 8))
+        );
+        assert_eq!(throttle.client_address(&HeaderMap::new(), ADDRESS), ADDRESS);
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::Hmac;
+use jwt::{AlgorithmType, PKeyWithDigest, SigningAlgorithm, VerifyingAlgorithm};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{Id, PKey, Private, Public},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error_ctx;
+
+/// A key new tokens can be signed with.
+#[derive(Clone)]
+pub(super) enum SigningKey {
+    Hmac(Hmac<Sha256>),
+    Private(Arc<PKeyWithDigest<Private>>),
+}
+
+/// A key tokens can be verified with.
+#[derive(Clone)]
+pub(super) enum VerifyingKey {
+    Hmac(Hmac<Sha256>),
+    Public(Arc<PKeyWithDigest<Public>>),
+}
+
+impl SigningAlgorithm for SigningKey {
+    fn algorithm_type(&self) -> AlgorithmType {
+        match self {
+            Self::Hmac(key) => SigningAlgorithm::algorithm_type(key),
+            Self::Private(key) => key.algorithm_type(),
+        }
+    }
+
+    fn sign(&self, header: &str, claims: &str) -> Result<String, jwt::Error> {
+        match self {
+            Self::Hmac(key) => key.sign(header, claims),
+            Self::Private(key) => key.sign(header, claims),
+        }
+    }
+}
+
+impl VerifyingAlgorithm for VerifyingKey {
+    fn algorithm_type(&self) -> AlgorithmType {
+        match self {
+            Self::Hmac(key) => VerifyingAlgorithm::algorithm_type(key),
+            Self::Public(key) => key.algorithm_type(),
+        }
+    }
+
+    fn verify_bytes(
+        &self,
+        header: &str,
+        claims: &str,
+        signature: &[u8],
+    ) -> Result<bool, jwt::Error> {
+        match self {
+            Self::Hmac(key) => key.verify_bytes(header, claims, signature),
+            Self::Public(key) => key.verify_bytes(header, claims, signature),
+        }
+    }
+}
+
+/// A public key as published in our JWK set (RFC 7517).
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Jwk {
+    kid: String,
+    alg: AlgorithmType,
+    #[serde(rename = "use")]
+    usage: &'static str,
+    #[serde(flatten)]
+    params: JwkParams,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kty")]
+enum JwkParams {
+    #[serde(rename = "RSA")]
+    Rsa { n: String, e: String },
+    #[serde(rename = "EC")]
+    Ec {
+        crv: &'static str,
+        x: String,
+        y: String,
+    },
+}
+
+impl JwkParams {
+    /// The RFC 7638 thumbprint of the key, used as its key ID.
+    fn thumbprint(&self) -> String {
+        // Required members only, in lexicographic order, without whitespace
+        let canonical = match self {
+            Self::Rsa { n, e } => format!(r#"{{"e":"{e}","kty":"RSA","n":"{n}"}}"#),
+            Self::Ec { crv, x, y } => {
+                format!(r#"{{"crv":"{crv}","kty":"EC","x":"{x}","y":"{y}"}}"#)
+            }
+        };
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+}
+
+/// An asymmetric key pair loaded from a PEM-encoded private key.
+pub(super) struct KeyPair {
+    pub kid: String,
+    pub signing: SigningKey,
+    pub verifying: VerifyingKey,
+    pub jwk: Jwk,
+}
+
+impl KeyPair {
+    /// Load an RSA (RS256) or P-256 (ES256) private key from PEM.
+    pub fn from_pem(pem: &[u8]) -> Result<Self> {
+        let private = PKey::private_key_from_pem(pem)
+            .with_context(error_ctx!("Failed to parse private key"))?;
+
+        let params = match private.id() {
+            Id::RSA => {
+                let rsa = private
+                    .rsa()
+                    .with_context(error_ctx!("Failed to read RSA key"))?;
+                if rsa.size() < 256 {
+                    bail!(error_ctx!("RSA keys must be at least 2048 bits")());
+                }
+                JwkParams::Rsa {
+                    n: URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                    e: URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+                }
+            }
+            Id::EC => {
+                let ec = private
+                    .ec_key()
+                    .with_context(error_ctx!("Failed to read EC key"))?;
+                if ec.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+                    bail!(error_ctx!("Only P-256 EC keys are supported")());
+                }
+
+                let mut x = BigNum::new()?;
+                let mut y = BigNum::new()?;
+                let mut ctx = BigNumContext::new()?;
+                ec.public_key()
+                    .affine_coordinates(ec.group(), &mut x, &mut y, &mut ctx)
+                    .with_context(error_ctx!("Failed to read EC public key"))?;
+                JwkParams::Ec {
+                    crv: "P-256",
+                    x: URL_SAFE_NO_PAD.encode(x.to_vec_padded(32)?),
+                    y: URL_SAFE_NO_PAD.encode(y.to_vec_padded(32)?),
+                }
+            }
+            _ => bail!(error_ctx!("Only RSA and EC private keys are supported")()),
+        };
+
+        let public = PKey::public_key_from_der(&private.public_key_to_der()?)
+            .with_context(error_ctx!("Failed to derive public key"))?;
+        let signing = PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: private,
+        };
+        let verifying = PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: public,
+        };
+
+        let kid = params.thumbprint();
+        let jwk = Jwk {
+            kid: kid.clone(),
+            alg: SigningAlgorithm::algorithm_type(&signing),
+            usage: "sig",
+            params,
+        };
+
+        Ok(Self {
+            kid,
+            signing: SigningKey::Private(Arc::new(signing)),
+            verifying: VerifyingKey::Public(Arc::new(verifying)),
+            jwk,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        rsa::Rsa,
+    };
+
+    use super::*;
+
+    pub fn rsa_pem() -> Vec<u8> {
+        let rsa = Rsa::generate(2048).unwrap();
+        PKey::from_rsa(rsa)
+            .unwrap()
+            .private_key_to_pem_pkcs8()
+            .unwrap()
+    }
+
+    pub fn ec_pem() -> Vec<u8> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let ec = EcKey::generate(&group).unwrap();
+        PKey::from_ec_key(ec)
+            .unwrap()
+            .private_key_to_pem_pkcs8()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rsa_key_pair() {
+        let pair = KeyPair::from_pem(&rsa_pem()).unwrap();
+        assert_eq!(pair.jwk.alg, AlgorithmType::Rs256);
+
+        let json = serde_json::to_value(&pair.jwk).unwrap();
+        assert_eq!(json["kty"], "RSA");
+        assert_eq!(json["use"], "sig");
+        assert_eq!(json["alg"], "RS256");
+        assert_eq!(json["kid"], pair.kid.as_str());
+        assert_eq!(json["e"], "AQAB");
+        assert!(json.get("d").is_none());
+    }
+
+    #[test]
+    fn test_ec_key_pair() {
+        let pair = KeyPair::from_pem(&ec_pem()).unwrap();
+        assert_eq!(pair.jwk.alg, AlgorithmType::Es256);
+
+        let json = serde_json::to_value(&pair.jwk).unwrap();
+        assert_eq!(json["kty"], "EC");
+        assert_eq!(json["crv"], "P-256");
+        assert_eq!(json["x"].as_str().unwrap().len(), 43);
+        assert_eq!(json["y"].as_str().unwrap().len(), 43);
+    }
+
+    #[test]
+    fn test_rejects_unsupported_keys() {
+        assert!(KeyPair::from_pem(b"not a key").is_err());
+
+        let small = PKey::from_rsa(Rsa::generate(1024).unwrap()).unwrap();
+        assert!(KeyPair::from_pem(&small.private_key_to_pem_pkcs8().unwrap()).is_err());
+
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let p384 = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        assert!(KeyPair::from_pem(&p384.private_key_to_pem_pkcs8().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_thumbprint() {
+        // Example from RFC 7638, section 3.1
+        let params = JwkParams::Rsa {
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_string(),
+            e: "AQAB".to_string(),
+        };
+        assert_eq!(
+            params.thumbprint(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+}
@@ -0,0 +1,581 @@
+mod keys;
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use jwt::{Header, SignWithKey, SigningAlgorithm, Token, VerifyWithKey, VerifyWithStore};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::Sha256;
+use uuid::Uuid;
+
+pub(crate) use keys::Jwk;
+use keys::{KeyPair, SigningKey, VerifyingKey};
+
+use crate::{
+    config::Config,
+    error_ctx,
+    models::{EmailClaim, NomerClaim},
+};
+
+/// Key ID under which `HMAC_SECRET` is registered.
+const LEGACY_KID: &str = "default";
+
+/// What a token is for, carried as its `typ` claim, so that one kind of
+/// token can never be passed off as another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenType {
+    /// Access and refresh tokens.
+    Session,
+    EmailVerification,
+}
+
+/// Issues and verifies the JWTs handed out to nomers.
+#[derive(Clone)]
+pub(crate) struct Tokens {
+    /// Every key tokens may be verified with, by key ID.
+    keys: BTreeMap<String, VerifyingKey>,
+    /// ID of the key new tokens are signed with.
+    signing_kid: String,
+    signing_key: SigningKey,
+    /// Public keys published for other services to verify our tokens.
+    jwks: Vec<Jwk>,
+    /// Verifies tokens issued before key IDs were used.
+    legacy_key: Option<Hmac<Sha256>>,
+    issuer: String,
+    audience: String,
+    access_lifetime: i64,
+    refresh_lifetime: i64,
+    verification_lifetime: i64,
+    leeway: i64,
+}
+
+impl Tokens {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut keys = BTreeMap::new();
+        let mut signing = None;
+        let mut jwks = Vec::new();
+
+        // An asymmetric key, if configured, signs everything; HMAC keys then
+        // only verify tokens issued before the switch
+        if let Some(path) = &config.token_signing_key {
+            let pem = std::fs::read(path)
+                .with_context(error_ctx!("Failed to read {}", path.display()))?;
+            let pair = KeyPair::from_pem(&pem)
+                .with_context(error_ctx!("Invalid signing key {}", path.display()))?;
+            keys.insert(pair.kid.clone(), pair.verifying);
+            signing = Some((pair.kid, pair.signing));
+            jwks.push(pair.jwk);
+        }
+
+        // Load the keyring; the first listed key signs, the rest only verify
+        if let Some(hmac_keys) = &config.hmac_keys {
+            for (kid, secret) in parse_hmac_keys(hmac_keys)? {
+                let hmac = Hmac::<Sha256>::new_from_slice(&secret)
+                    .with_context(error_ctx!("Failed to create HMAC instance for key {kid}"))?;
+                if keys.contains_key(&kid) {
+                    bail!(error_ctx!("Duplicate key ID {kid}")());
+                }
+                signing.get_or_insert_with(|| (kid.clone(), SigningKey::Hmac(hmac.clone())));
+                keys.insert(kid, VerifyingKey::Hmac(hmac));
+            }
+        }
+
+        // The single secret predates key IDs; it verifies tokens without a
+        // `kid`, and signs only when no keyring is configured
+        let legacy_key = match &config.hmac_secret {
+            Some(secret) => {
+                let hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                    .with_context(error_ctx!("Failed to create HMAC instance"))?;
+                keys.entry(LEGACY_KID.to_string())
+                    .or_insert_with(|| VerifyingKey::Hmac(hmac.clone()));
+                signing.get_or_insert_with(|| {
+                    (LEGACY_KID.to_string(), SigningKey::Hmac(hmac.clone()))
+                });
+                Some(hmac)
+            }
+            None => None,
+        };
+
+        let Some((signing_kid, signing_key)) = signing else {
+            bail!(error_ctx!(
+                "One of TOKEN_SIGNING_KEY, HMAC_KEYS or HMAC_SECRET must be set"
+            )());
+        };
+
+        Ok(Self {
+            keys,
+            signing_kid,
+            signing_key,
+            jwks,
+            legacy_key,
+            issuer: config.token_issuer.clone(),
+            audience: config.token_audience.clone(),
+            access_lifetime: config.access_token_lifetime,
+            refresh_lifetime: config.refresh_token_lifetime,
+            verification_lifetime: config.email_verification_lifetime,
+            leeway: config.token_leeway,
+        })
+    }
+
+    /// Lifetime of a refresh token, in seconds.
+    pub fn refresh_lifetime(&self) -> i64 {
+        self.refresh_lifetime
+    }
+
+    /// Public keys other services may verify our tokens with.
+    pub fn jwks(&self) -> &[Jwk] {
+        &self.jwks
+    }
+
+    /// Allowed clock skew, in seconds, when checking token timestamps.
+    pub fn leeway(&self) -> i64 {
+        self.leeway
+    }
+
+    /// Make a fresh claim for `subject`, valid from now on.
+    pub fn claim(&self, subject: String, is_access: bool) -> NomerClaim {
+        let now = Utc::now().timestamp();
+        let lifetime = if is_access {
+            self.access_lifetime
+        } else {
+            self.refresh_lifetime
+        };
+
+        NomerClaim {
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            typ: Some(TokenType::Session),
+            sub: subject,
+            exp: now + lifetime,
+            iat: now,
+            acc: is_access,
+            generation: 0,
+            jti: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Make a claim proving that nomer `nomer_id` received mail at `email`,
+    /// for the stored verification `token_id`.
+    pub fn email_claim(&self, nomer_id: i64, email: String, token_id: String) -> EmailClaim {
+        let now = Utc::now().timestamp();
+
+        EmailClaim {
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            typ: TokenType::EmailVerification,
+            sub: nomer_id.to_string(),
+            email,
+            exp: now + self.verification_lifetime,
+            iat: now,
+            jti: token_id,
+        }
+    }
+
+    /// Sign `claim` with the current key, naming it in the `kid` header.
+    pub fn sign<C: Serialize>(&self, claim: &C) -> Option<String> {
+        let header = Header {
+            algorithm: self.signing_key.algorithm_type(),
+            key_id: Some(self.signing_kid.clone()),
+            ..Header::default()
+        };
+
+        Token::new(header, claim)
+            .sign_with_key(&self.signing_key)
+            .ok()
+            .map(|token| token.as_str().to_string())
+    }
+
+    /// Check the signature of `token` and that it was issued by us, for us.
+    ///
+    /// Timestamps are not checked; see [`NomerClaim::validate`].
+    pub fn verify(&self, token: &str) -> Result<NomerClaim, (StatusCode, &'static str)> {
+        let claim: NomerClaim = self.verify_signature(token)?;
+
+        // Tokens issued before issuers and audiences were checked carry
+        // neither; they are let through until they could no longer be valid.
+        // Remove once a refresh token lifetime has passed since the upgrade.
+        if claim.iss.is_empty() && claim.aud.is_empty() {
+            if Utc::now().timestamp() - claim.iat > self.refresh_lifetime {
+                return Err((StatusCode::UNAUTHORIZED, "Invalid token issuer"));
+            }
+            return Ok(claim);
+        }
+
+        self.check_origin(&claim.iss, &claim.aud)?;
+        if claim.typ != Some(TokenType::Session) {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token type"));
+        }
+        Ok(claim)
+    }
+
+    /// Like [`Tokens::verify`], for email verification tokens.
+    ///
+    /// Timestamps are not checked; see [`EmailClaim::validate`].
+    pub fn verify_email(&self, token: &str) -> Result<EmailClaim, (StatusCode, &'static str)> {
+        let claim: EmailClaim = self.verify_signature(token)?;
+        self.check_origin(&claim.iss, &claim.aud)?;
+        if claim.typ != TokenType::EmailVerification {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token type"));
+        }
+        Ok(claim)
+    }
+
+    fn verify_signature<C: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<C, (StatusCode, &'static str)> {
+        let Ok(unverified) = Token::<Header, C, _>::parse_unverified(token) else {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token"));
+        };
+
+        let verified = match (&unverified.header().key_id, &self.legacy_key) {
+            (Some(kid), _) if !self.keys.contains_key(kid) => {
+                return Err((StatusCode::UNAUTHORIZED, "Unknown signing key"));
+            }
+            (Some(_), _) => unverified.verify_with_store(&self.keys),
+            (None, Some(legacy_key)) => unverified.verify_with_key(legacy_key),
+            (None, None) => return Err((StatusCode::UNAUTHORIZED, "Unknown signing key")),
+        };
+        let Ok(verified) = verified else {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token"));
+        };
+        let (_, claim): (Header, C) = verified.into();
+
+        Ok(claim)
+    }
+
+    /// Check that a token was issued by us, for us.
+    fn check_origin(&self, iss: &str, aud: &str) -> Result<(), (StatusCode, &'static str)> {
+        if iss != self.issuer {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token issuer"));
+        }
+
+        if aud != self.audience {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token audience"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse `HMAC_KEYS`: a comma-separated list of `<kid>:<Base64 secret>`.
+fn parse_hmac_keys(hmac_keys: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut keys: Vec<(String, Vec<u8>)> = Vec::new();
+
+    for entry in hmac_keys.split(',').map(str::trim) {
+        let Some((kid, secret)) = entry.split_once(':') else {
+            bail!(error_ctx!(
+                "HMAC key entry is not of the form <kid>:<secret>"
+            )());
+        };
+        let kid = kid.trim();
+        if kid.is_empty() {
+            bail!(error_ctx!("HMAC key ID cannot be empty")());
+        }
+        if keys.iter().any(|(k, _)| k == kid) {
+            bail!(error_ctx!("Duplicate HMAC key ID {kid}")());
+        }
+
+        let secret = STANDARD
+            .decode(secret.trim())
+            .with_context(error_ctx!("HMAC key {kid} is not valid Base64"))?;
+        keys.push((kid.to_string(), secret));
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+impl Tokens {
+    pub fn for_testing() -> Self {
+        let hmac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        Self {
+            keys: BTreeMap::from([("test".to_string(), VerifyingKey::Hmac(hmac.clone()))]),
+            signing_kid: "test".to_string(),
+            signing_key: SigningKey::Hmac(hmac),
+            jwks: Vec::new(),
+            legacy_key: None,
+            issuer: "nomnom-test".to_string(),
+            audience: "nomnom-test".to_string(),
+            access_lifetime: 60,
+            refresh_lifetime: 120,
+            verification_lifetime: 300,
+            leeway: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim() {
+        let tokens = Tokens::for_testing();
+
+        let access = tokens.claim("1".to_string(), true);
+        assert!(access.acc);
+        assert_eq!(access.exp - access.iat, 60);
+        assert_eq!(access.iss, "nomnom-test");
+        assert_eq!(access.aud, "nomnom-test");
+
+        let refresh = tokens.claim("1".to_string(), false);
+        assert!(!refresh.acc);
+        assert_eq!(refresh.exp - refresh.iat, 120);
+
+        // Every claim gets its own ID
+        assert_ne!(access.jti, refresh.jti);
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let tokens = Tokens::for_testing();
+        let claim = tokens.claim("1".to_string(), true);
+        let token = tokens.sign(&claim).unwrap();
+
+        let verified = tokens.verify(&token).unwrap();
+        assert_eq!(verified.sub, "1");
+        assert_eq!(verified.jti, claim.jti);
+    }
+
+    fn hmac(secret: &[u8]) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(secret).unwrap()
+    }
+
+    /// Test tokens signing with the first HMAC key and verifying with all.
+    fn with_hmac_keys(keys: &[(&str, &[u8])]) -> Tokens {
+        let (signing_kid, signing_secret) = keys[0];
+        Tokens {
+            keys: keys
+                .iter()
+                .map(|(kid, secret)| (kid.to_string(), VerifyingKey::Hmac(hmac(secret))))
+                .collect(),
+            signing_kid: signing_kid.to_string(),
+            signing_key: SigningKey::Hmac(hmac(signing_secret)),
+            ..Tokens::for_testing()
+        }
+    }
+
+    /// Test tokens signing with an asymmetric key pair.
+    fn with_key_pair(pem: &[u8]) -> Tokens {
+        let pair = KeyPair::from_pem(pem).unwrap();
+        Tokens {
+            keys: BTreeMap::from([(pair.kid.clone(), pair.verifying)]),
+            signing_kid: pair.kid,
+            signing_key: pair.signing,
+            jwks: vec![pair.jwk],
+            ..Tokens::for_testing()
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_key() {
+        let tokens = Tokens::for_testing();
+        let other = with_hmac_keys(&[("test", b"other")]);
+        let token = other.sign(&other.claim("1".to_string(), true)).unwrap();
+
+        assert_eq!(
+            tokens.verify(&token).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token")
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_other_issuer_and_audience() {
+        let tokens = Tokens::for_testing();
+
+        // Same secret, different deployment
+        let staging = Tokens {
+            issuer: "nomnom-staging".to_string(),
+            ..Tokens::for_testing()
+        };
+        let token = staging.sign(&staging.claim("1".to_string(), true)).unwrap();
+        assert_eq!(
+            tokens.verify(&token).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token issuer")
+        );
+
+        let other_app = Tokens {
+            audience: "other-app".to_string(),
+            ..Tokens::for_testing()
+        };
+        let token = other_app
+            .sign(&other_app.claim("1".to_string(), true))
+            .unwrap();
+        assert_eq!(
+            tokens.verify(&token).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token audience")
+        );
+    }
+
+    #[test]
+    fn test_verify_without_issuer_and_audience() {
+        let tokens = Tokens::for_testing();
+        let legacy = |iat: i64| {
+            #[derive(Serialize)]
+            struct LegacyClaim {
+                sub: String,
+                exp: i64,
+                iat: i64,
+                acc: bool,
+            }
+
+            tokens
+                .sign(&LegacyClaim {
+                    sub: "test@test.com".to_string(),
+                    exp: iat + 60,
+                    iat,
+                    acc: true,
+                })
+                .unwrap()
+        };
+
+        // Accepted while still within a refresh token lifetime of issue
+        let now = Utc::now().timestamp();
+        let claim = tokens.verify(&legacy(now - 60)).unwrap();
+        assert_eq!(claim.sub, "test@test.com");
+        assert!(claim.jti.is_empty());
+
+        assert_eq!(
+            tokens.verify(&legacy(now - 121)).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token issuer")
+        );
+
+        // Only both missing counts as a token from before the upgrade
+        let mut claim = tokens.claim("1".to_string(), true);
+        claim.iss = String::new();
+        assert_eq!(
+            tokens.verify(&tokens.sign(&claim).unwrap()).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token issuer")
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_other_token_type() {
+        let tokens = Tokens::for_testing();
+
+        // Shaped like a session claim, but for verifying an email
+        let mut claim = tokens.claim("1".to_string(), true);
+        claim.typ = Some(TokenType::EmailVerification);
+        assert_eq!(
+            tokens.verify(&tokens.sign(&claim).unwrap()).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token type")
+        );
+
+        let mut claim = tokens.email_claim(1, "test@test.com".to_string(), "id".to_string());
+        claim.typ = TokenType::Session;
+        assert_eq!(
+            tokens
+                .verify_email(&tokens.sign(&claim).unwrap())
+                .unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token type")
+        );
+    }
+
+    #[test]
+    fn test_sign_names_current_key() {
+        let tokens = Tokens::for_testing();
+        let token = tokens.sign(&tokens.claim("1".to_string(), true)).unwrap();
+
+        let parsed = Token::<Header, NomerClaim, _>::parse_unverified(&token).unwrap();
+        assert_eq!(parsed.header().key_id.as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn test_verify_with_previous_key() {
+        let old = Tokens::for_testing();
+        let token = old.sign(&old.claim("1".to_string(), true)).unwrap();
+
+        // Rotated: signs with "next", still verifies "test"
+        let rotated = with_hmac_keys(&[("next", b"next secret"), ("test", b"secret")]);
+        assert!(rotated.verify(&token).is_ok());
+
+        let new_token = rotated.sign(&rotated.claim("1".to_string(), true)).unwrap();
+        assert!(rotated.verify(&new_token).is_ok());
+
+        // Once retired, the old key no longer verifies anything
+        let retired = with_hmac_keys(&[("next", b"next secret")]);
+        assert_eq!(
+            retired.verify(&token).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Unknown signing key")
+        );
+        assert!(retired.verify(&new_token).is_ok());
+    }
+
+    #[test]
+    fn test_verify_without_key_id() {
+        let tokens = Tokens::for_testing();
+        let claim = tokens.claim("1".to_string(), true);
+        let token = claim.sign_with_key(&hmac(b"secret")).unwrap();
+
+        // Only accepted when a legacy key is configured
+        assert_eq!(
+            tokens.verify(&token).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Unknown signing key")
+        );
+
+        let with_legacy = Tokens {
+            legacy_key: Some(hmac(b"secret")),
+            ..Tokens::for_testing()
+        };
+        assert!(with_legacy.verify(&token).is_ok());
+    }
+
+    #[test]
+    fn test_parse_hmac_keys() {
+        let keys = parse_hmac_keys("2025-09:c2VjcmV0, 2025-06:b2xk").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                ("2025-09".to_string(), b"secret".to_vec()),
+                ("2025-06".to_string(), b"old".to_vec()),
+            ]
+        );
+
+        assert!(parse_hmac_keys("c2VjcmV0").is_err());
+        assert!(parse_hmac_keys(":c2VjcmV0").is_err());
+        assert!(parse_hmac_keys("a:c2VjcmV0,a:b2xk").is_err());
+        assert!(parse_hmac_keys("a:not base64!").is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_with_key_pair() {
+        for pem in [keys::tests::rsa_pem(), keys::tests::ec_pem()] {
+            let tokens = with_key_pair(&pem);
+            let token = tokens.sign(&tokens.claim("1".to_string(), true)).unwrap();
+            assert_eq!(tokens.verify(&token).unwrap().sub, "1");
+
+            // Keys are not interchangeable between deployments
+            let other = with_key_pair(&pem);
+            assert!(other.verify(&token).is_ok());
+            let other = with_key_pair(&keys::tests::ec_pem());
+            assert!(other.verify(&token).is_err());
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_algorithm_confusion() {
+        let tokens = with_key_pair(&keys::tests::rsa_pem());
+
+        // An HMAC token naming the RSA key, "signed" with its public key
+        let header = Header {
+            algorithm: jwt::AlgorithmType::Hs256,
+            key_id: Some(tokens.signing_kid.clone()),
+            ..Header::default()
+        };
+        let public_jwk = serde_json::to_string(&tokens.jwks()[0]).unwrap();
+        let forged = Token::new(header, tokens.claim("1".to_string(), true))
+            .sign_with_key(&hmac(public_jwk.as_bytes()))
+            .unwrap();
+
+        assert_eq!(
+            tokens.verify(forged.as_str()).unwrap_err(),
+            (StatusCode::UNAUTHORIZED, "Invalid token")
+        );
+    }
+}